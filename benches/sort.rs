@@ -0,0 +1,110 @@
+use bitonic_sorter::fourth::{
+    sort, sort_by, sort_u32_radix_hybrid, sort_with_threshold, PARALLEL_THRESHOLD,
+};
+use bitonic_sorter::utils::new_u32_vec;
+use bitonic_sorter::SortOrder;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::cmp::Ordering;
+
+// ソートする要素数（2のべき乗の指数）
+const BITS: [u32; 3] = [16, 18, 20];
+
+// 比較する並列処理のしきい値。usize::MAXなら常に順番にソートする
+const THRESHOLDS: [usize; 4] = [1024, 4096, 16384, usize::MAX];
+
+// 要素数としきい値の組み合わせごとに、fourth::sort_with_thresholdの実行時間を測る
+// 標準ライブラリのslice::sortも同じデータで測り、比べられるようにする
+fn bench_sort(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sort_u32");
+    // 要素数が多いと1回のソートに時間がかかるので、サンプル数を減らす
+    group.sample_size(10);
+
+    for &bits in &BITS {
+        let len = 2usize.pow(bits);
+        let x = new_u32_vec(len);
+
+        for &threshold in &THRESHOLDS {
+            let name = if threshold == usize::MAX {
+                "bitonic/sequential".to_string()
+            } else {
+                format!("bitonic/threshold={}", threshold)
+            };
+            let id = BenchmarkId::new(name, len);
+            group.bench_with_input(id, &x, |b, x| {
+                // 毎回ソート前のデータを複製し、複製にかかる時間は測定に含めない
+                b.iter_batched_ref(
+                    || x.clone(),
+                    |v| sort_with_threshold(v, SortOrder::Ascending, threshold).unwrap(),
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+
+        group.bench_with_input(BenchmarkId::new("std", len), &x, |b, x| {
+            b.iter_batched_ref(|| x.clone(), |v| v.sort(), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+// 2^22要素では、compare_and_swapの比較のループ自体も並列イテレータで処理される
+// デフォルトのしきい値と、すべて順番に処理する場合とを比べる
+// 上位8ビットで振り分けてからソートするsort_u32_radix_hybridも同じデータで測る
+fn bench_sort_large(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sort_u32_large");
+    group.sample_size(10);
+
+    let len = 2usize.pow(22);
+    let x = new_u32_vec(len);
+    for &(name, threshold) in &[
+        ("bitonic/parallel", PARALLEL_THRESHOLD),
+        ("bitonic/sequential", usize::MAX),
+    ] {
+        group.bench_with_input(BenchmarkId::new(name, len), &x, |b, x| {
+            b.iter_batched_ref(
+                || x.clone(),
+                |v| sort_with_threshold(v, SortOrder::Ascending, threshold).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.bench_with_input(BenchmarkId::new("radix_hybrid", len), &x, |b, x| {
+        b.iter_batched_ref(
+            || x.clone(),
+            |v| sort_u32_radix_hybrid(v, SortOrder::Ascending).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+// sortはOrd::cmpを呼ぶクロージャを型引数として渡すので、比較はインライン展開される
+// 同じ比較を&dynのトレイトオブジェクト越しに呼び出す（間接呼び出しになる）場合と比べる
+fn bench_comparator_dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("comparator_dispatch");
+    group.sample_size(10);
+
+    let len = 2usize.pow(20);
+    let x = new_u32_vec(len);
+    group.bench_with_input(BenchmarkId::new("sort", len), &x, |b, x| {
+        b.iter_batched_ref(
+            || x.clone(),
+            |v| sort(v, SortOrder::Ascending).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    let comparator: &(dyn Fn(&u32, &u32) -> Ordering + Sync) = &|a, b| a.cmp(b);
+    group.bench_with_input(BenchmarkId::new("dyn_comparator", len), &x, |b, x| {
+        b.iter_batched_ref(
+            || x.clone(),
+            // black_boxで、コンパイラがトレイトオブジェクトの中身を見抜いて直接呼ぶのを防ぐ
+            |v| sort_by(v, &|a: &u32, b: &u32| black_box(comparator)(a, b)).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_sort, bench_sort_large, bench_comparator_dispatch);
+criterion_main!(benches);
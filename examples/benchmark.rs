@@ -1,11 +1,9 @@
-use num_cpus;
-
-use bitonic_sorter::SortOrder;
+use bitonic_sorter::{SortError, SortOrder};
 // 第3段階のsort関数をseq_sortという別名で使用する
 use bitonic_sorter::third::sort as seq_sort;
 // 第4段階のsort関数をpar_sortという別名で使用する
 use bitonic_sorter::fourth::sort as par_sort;
-use bitonic_sorter::utils::{is_sorted_descending, is_sorted_ascending, new_u32_vec};
+use bitonic_sorter::utils::{is_sorted_ascending, new_u32_vec};
 
 use std::{env, f64};
 use std::str::FromStr;
@@ -24,7 +22,7 @@ fn main() {
         // ステータスコード 1 で終了する
         eprintln!(
             "Using {} <number of elements in bits>",
-            env::args().nth(0).unwrap()
+            env::args().next().unwrap()
         );
         std::process::exit(1);
     }
@@ -63,7 +61,7 @@ fn run_sorts(bits: u32) {
 
 fn timed_sort<F>(sorter: &F, len: usize, name: &str) -> f64
 where
-    F: Fn(&mut [u32], &SortOrder) -> Result<(), String>
+    F: Fn(&mut [u32], SortOrder) -> Result<(), SortError>
 {
     // 要素数lenのu32型ベクタを生成する
     let mut x = new_u32_vec(len);
@@ -71,7 +69,7 @@ where
     // sorter関数を呼び出すことでソートを実行する
     // かかった時間（dur）を記録する
     let start = Instant::now();
-    sorter(&mut x, &SortOrder::Ascending).expect("Failed to sort: ");
+    sorter(&mut x, SortOrder::Ascending).expect("Failed to sort: ");
     let dur = start.elapsed();
 
     // ソートした要素数とかかった時間（秒）を表示する
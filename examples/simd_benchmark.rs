@@ -0,0 +1,39 @@
+use bitonic_sorter::SortOrder;
+// 第4段階のsort関数を順次処理にしてスカラー版として使う
+use bitonic_sorter::fourth::sort_with_threshold;
+use bitonic_sorter::simd::sort_u32 as simd_sort;
+use bitonic_sorter::utils::{is_sorted_ascending, new_u32_vec};
+
+use std::env;
+use std::str::FromStr;
+use std::time::Instant;
+
+// スカラー版とSIMD版のバイトニックソートの速さを比べる
+// 実行例：cargo run --release --features simd --example simd_benchmark 22
+fn main() {
+    let bits = match env::args().nth(1) {
+        Some(n) => u32::from_str(&n).expect("error parsing argument"),
+        None => 22,
+    };
+    let len = 1 << bits;
+    println!("sorting {} integers", len);
+
+    let scalar = timed(len, "scalar", |x| {
+        sort_with_threshold(x, SortOrder::Ascending, usize::MAX).expect("Failed to sort: ")
+    });
+    let simd = timed(len, "simd", |x| {
+        simd_sort(x, SortOrder::Ascending).expect("Failed to sort: ")
+    });
+
+    println!("speed up: {:.2}x", scalar / simd);
+}
+
+fn timed<F: Fn(&mut [u32])>(len: usize, name: &str, sorter: F) -> f64 {
+    let mut x = new_u32_vec(len);
+    let start = Instant::now();
+    sorter(&mut x);
+    let secs = start.elapsed().as_secs_f64();
+    println!("{}: sorted {} integers in {} seconds", name, len, secs);
+    assert!(is_sorted_ascending(&x));
+    secs
+}
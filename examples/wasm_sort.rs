@@ -0,0 +1,27 @@
+// wasm32-unknown-unknownのようにスレッドを作れない環境で、ソートを使う例
+// rayonもrandも使わない構成でビルドする。fourthのソートはすべて順番に実行される
+//
+//     cargo build --example wasm_sort --no-default-features --target wasm32-unknown-unknown
+//
+// ホストでもcargo run --example wasm_sort --no-default-featuresで実行できる
+use bitonic_sorter::fourth::sort;
+use bitonic_sorter::utils::is_sorted_ascending;
+use bitonic_sorter::SortOrder;
+
+fn main() {
+    // randクレートを使わずにテストデータを作る（xorshiftによる簡単な疑似乱数）
+    let mut state = 2_463_534_242u32;
+    let mut x: Vec<u32> = (0..1000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        })
+        .collect();
+
+    sort(&mut x, SortOrder::Ascending).expect("failed to sort");
+    assert!(is_sorted_ascending(&x));
+    // wasm32-unknown-unknownでは標準出力はどこにも表示されない
+    println!("sorted {} elements: {:?} .. {:?}", x.len(), &x[..3], &x[x.len() - 3..]);
+}
@@ -0,0 +1,80 @@
+use core::cmp::Ordering;
+
+// 複数のキーで比較するcomparatorを読みやすく書くための関数
+// 例えば「年齢の昇順、年齢が同じなら名前の降順」は次のように書ける
+//
+//     sort_by(&mut x, &|a, b| compose([a.age.cmp(&b.age), field_desc(a.name.cmp(&b.name))]))
+
+// キーの比較結果を反転して、そのキーについて降順に並べる。ord.reverse()と同じ
+pub fn field_desc(ord: Ordering) -> Ordering {
+    ord.reverse()
+}
+
+// 前のキーから順に見て、最初にEqualでなかった比較結果を返す。すべてEqualならEqual
+// a.cmp(b).then(...).then(...)と同じだが、各キーを配列の要素として並べて書ける
+// 配列を作る時点ですべてのキーを比較するので、後ろのキーの比較が重い時は
+// Ordering::then_withで必要な時だけ比較する方がよい
+pub fn compose<const N: usize>(orderings: [Ordering; N]) -> Ordering {
+    orderings
+        .iter()
+        .find(|&&ordering| ordering != Ordering::Equal)
+        .copied()
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compose, field_desc};
+    use crate::fourth::sort_by;
+    use core::cmp::Ordering::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Entry {
+        team: &'static str,
+        score: u32,
+        name: &'static str,
+    }
+
+    fn entry(team: &'static str, score: u32, name: &'static str) -> Entry {
+        Entry { team, score, name }
+    }
+
+    #[test]
+    fn compose_orderings() {
+        assert_eq!(compose([Equal, Less, Greater]), Less);
+        assert_eq!(compose([Equal, Equal]), Equal);
+        assert_eq!(compose([]), Equal);
+        assert_eq!(field_desc(Less), Greater);
+        assert_eq!(field_desc(Equal), Equal);
+    }
+
+    #[test]
+    fn sort_by_mixed_directions() {
+        let mut x = vec![
+            entry("red", 10, "Taro"),
+            entry("blue", 30, "Hanako"),
+            entry("red", 30, "Kyoko"),
+            entry("blue", 30, "Ryosuke"),
+            entry("red", 10, "Akira"),
+        ];
+        // チームの昇順、同じチームなら得点の降順、得点も同じなら名前の昇順
+        let comparator = |a: &Entry, b: &Entry| {
+            compose([
+                a.team.cmp(b.team),
+                field_desc(a.score.cmp(&b.score)),
+                a.name.cmp(b.name),
+            ])
+        };
+        assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+        assert_eq!(
+            x,
+            vec![
+                entry("blue", 30, "Hanako"),
+                entry("blue", 30, "Ryosuke"),
+                entry("red", 30, "Kyoko"),
+                entry("red", 10, "Akira"),
+                entry("red", 10, "Taro"),
+            ]
+        );
+    }
+}
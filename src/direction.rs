@@ -0,0 +1,38 @@
+use core::cmp::Ordering;
+
+// ソートの向きを型で表すためのマーカー型
+// SortOrderは実行時の値だが、こちらは型引数として渡すので、向きがコンパイル時に決まる
+// 処理の流れを組み立てる時に、向きを取り違えるとコンパイルエラーになるようにできる
+//
+//     fourth::sort_dir::<Descending, _>(&mut x)
+
+// クレートの外からDirectionを実装できないようにするための、非公開のトレイト
+mod sealed {
+    pub trait Sealed {}
+}
+
+// ソートの向きを表すトレイト。AscendingとDescendingだけが実装する
+// SWAP_CONDITIONはGreaterかLessでなければならないので、クレートの外からは実装できない
+pub trait Direction: sealed::Sealed {
+    // comparatorで前の要素と後ろの要素を比較した結果がこの値なら、2つを交換する
+    const SWAP_CONDITION: Ordering;
+}
+
+// 昇順を表すマーカー型
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ascending;
+
+// 降順を表すマーカー型
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Descending;
+
+impl sealed::Sealed for Ascending {}
+impl sealed::Sealed for Descending {}
+
+impl Direction for Ascending {
+    const SWAP_CONDITION: Ordering = Ordering::Greater;
+}
+
+impl Direction for Descending {
+    const SWAP_CONDITION: Ordering = Ordering::Less;
+}
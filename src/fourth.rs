@@ -1,223 +1,2249 @@
-use super::SortOrder;
-use rayon;
-use std::cmp::Ordering;
+use super::direction::{Ascending, Descending, Direction};
+use super::{Comparator, IndexInt, SortError, SortOrder, SortStats};
+#[cfg(feature = "std")]
+use super::TrySortError;
+use super::utils::{
+    is_bitonic_by, is_sorted_by, next_power_of_two_len, permute_with, power_of_two_exponent,
+};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::marker::PhantomData;
+use core::ops::{Deref, Range};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
 
-// 並列に処理するかを決めるしきい値
-const PARALLEL_THRESHOLD: usize = 4096;
+// loggingフィーチャーが有効ならlog::trace!で出力する
+// 無効なら何も生成しないので、引数の評価も含めて負荷はかからない
+#[cfg(feature = "logging")]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        log::trace!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "logging"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {};
+}
+
+// 並列に処理するかを決めるしきい値（sortとsort_byが使うデフォルト値）
+pub const PARALLEL_THRESHOLD: usize = 4096;
+
+// compare_and_swapの比較のループ自体を並列に処理するかを決めるしきい値
+// 比較の組がこれより少ないとタスクを分ける負荷の方が大きくなるので、とても大きな区間だけを対象にする
+const PARALLEL_COMPARE_THRESHOLD: usize = 1 << 16;
+
+// 2つのクロージャを並列に実行する
+// parallelフィーチャーが無効でscopedフィーチャーが有効なら、rayonの代わりにスレッドを作って実行する
+// どちらも無効なら順番に実行する
+#[cfg(feature = "parallel")]
+fn join<A, B>(oper_a: A, oper_b: B)
+where
+    A: FnOnce() + Send,
+    B: FnOnce() + Send,
+{
+    rayon::join(oper_a, oper_b);
+}
+
+#[cfg(all(feature = "scoped", not(feature = "parallel")))]
+fn join<A, B>(oper_a: A, oper_b: B)
+where
+    A: FnOnce() + Send,
+    B: FnOnce() + Send,
+{
+    // oper_aは新しいスレッドで、oper_bは呼び出し元のスレッドで実行する
+    // scopeを抜ける時に、作ったスレッドの終了を待つ
+    std::thread::scope(|s| {
+        s.spawn(oper_a);
+        oper_b();
+    });
+}
+
+#[cfg(not(any(feature = "parallel", feature = "scoped")))]
+fn join<A, B>(oper_a: A, oper_b: B)
+where
+    A: FnOnce() + Send,
+    B: FnOnce() + Send,
+{
+    oper_a();
+    oper_b();
+}
+
+// ソートの間、再帰呼び出しで共有される設定とカウンタ
+// rayon::joinで複数のスレッドから参照されるので、Syncな型だけを持たせる
+#[derive(Clone, Copy)]
+struct Context<'a> {
+    threshold: usize,               // 並列に処理するかを決めるしきい値
+    parallel_depth: usize,          // あと何段の再帰まで並列に処理してよいか
+    counters: Option<&'a Counters>, // 比較と交換の回数を数える場合はSome
+    cancel: Option<&'a AtomicBool>, // 中断できるようにする場合はSome
+    swapped: Option<&'a AtomicBool>, // 要素の交換が起きたかを知らせる場合はSome
+    // comparatorがOrd::cmpで、全順序であることがわかっている場合はtrue
+    // デバッグビルドでマージの前にバイトニック列かを確かめるのは、この場合だけにする
+    total_order: bool,
+}
+
+// 並列に処理する再帰の段数の既定値
+// scopedフィーチャーではjoinのたびに新しいスレッドを作るので、段数を制限しないと
+// 大きなスライスで数千のスレッドが同時に作られ、作れなくなった時点でパニックする
+// そこで同時に動くスレッドがCPUの数程度になるよう、段数をlog2(CPUの数)（切り上げ）にする
+#[cfg(all(feature = "scoped", not(feature = "parallel")))]
+fn default_parallel_depth() -> usize {
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    cpus.next_power_of_two().trailing_zeros() as usize
+}
+
+// rayonはスレッドプールのスレッドで処理するので、段数を制限しない
+#[cfg(not(all(feature = "scoped", not(feature = "parallel"))))]
+fn default_parallel_depth() -> usize {
+    usize::MAX
+}
+
+impl<'a> Context<'a> {
+    fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            parallel_depth: default_parallel_depth(),
+            counters: None,
+            cancel: None,
+            swapped: None,
+            total_order: false,
+        }
+    }
+
+    // 分割後の要素数がlenの2つの区間を並列に処理するなら、その再帰で使うContextを返す
+    // 並列に処理できる段数を1つ減らすので、並列に処理されるタスクは2^parallel_depth個までになる
+    fn parallel(&self, len: usize) -> Option<Context<'a>> {
+        if len >= self.threshold && self.parallel_depth > 0 {
+            Some(Context {
+                parallel_depth: self.parallel_depth - 1,
+                ..*self
+            })
+        } else {
+            None
+        }
+    }
+
+    // compare_and_swapで比較するlen組を、並列イテレータで分担して処理するならtrueを返す
+    // 順番に処理するよう指定された場合（thresholdがusize::MAXなど）は並列にしない
+    fn parallel_compare(&self, len: usize) -> bool {
+        cfg!(feature = "parallel")
+            && len >= self.threshold.max(PARALLEL_COMPARE_THRESHOLD)
+            && self.parallel_depth > 0
+    }
+
+    // 中断が要求されていたらtrueを返す
+    fn is_cancelled(&self) -> bool {
+        match self.cancel {
+            Some(cancel) => cancel.load(AtomicOrdering::Relaxed),
+            None => false,
+        }
+    }
+
+    // 要素の交換が起きたことを知らせる
+    // フラグは一度trueにするだけなので、交換の回数を数えるより負荷が小さい
+    fn mark_swapped(&self) {
+        if let Some(swapped) = self.swapped {
+            swapped.store(true, AtomicOrdering::Relaxed);
+        }
+    }
+}
+
+// 比較と交換の回数。複数のスレッドから加算されるのでアトミックな型を使う
+// 64ビットのアトミック操作がない組み込み向けのターゲットでも使えるよう、AtomicUsizeにしている
+#[derive(Default)]
+struct Counters {
+    comparisons: AtomicUsize,
+    swaps: AtomicUsize,
+    equals: AtomicUsize,
+}
+
+impl Counters {
+    fn stats(&self) -> SortStats {
+        SortStats {
+            comparisons: self.comparisons.load(AtomicOrdering::Relaxed) as u64,
+            swaps: self.swaps.load(AtomicOrdering::Relaxed) as u64,
+            equals: self.equals.load(AtomicOrdering::Relaxed) as u64,
+        }
+    }
+}
+
+// do_sortが再帰せずに挿入ソートで並べる区間の要素数の上限
+// これ以下の区間では、再帰呼び出しの負荷の方が比較と交換より大きくなる
+const SMALL_SORT_LEN: usize = 16;
+
+// FORWARDがtrueならcomparatorの順序に、falseなら逆順に並べる挿入ソート
+// 要素数がSMALL_SORT_LEN以下の区間にだけ使うので、O(n^2)でも比較の回数は少ない
+// 要素を1回でも交換したらtrueを返す
+fn insertion_sort<T, F, const FORWARD: bool>(x: &mut [T], comparator: &F) -> bool
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let out_of_order = if FORWARD {
+        Ordering::Greater
+    } else {
+        Ordering::Less
+    };
+    let mut swapped = false;
+    for i in 1..x.len() {
+        let mut j = i;
+        while j > 0 && comparator(&x[j - 1], &x[j]) == out_of_order {
+            x.swap(j - 1, j);
+            swapped = true;
+            j -= 1;
+        }
+    }
+    swapped
+}
 
-fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
+fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F, ctx: &Context)
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
+    // 中断が要求されていたら、それ以上は再帰せずに戻る
+    // 挿入ソートで並べる小さな区間も、中断された後は並べない
+    if ctx.is_cancelled() {
+        return;
+    }
+    // 小さな区間は再帰せず、挿入ソートで並べる
+    // 比較の回数を数える場合は、回数がバイトニックソートのものと変わらないよう再帰を続ける
+    if x.len() <= SMALL_SORT_LEN && ctx.counters.is_none() {
+        let swapped = if forward {
+            insertion_sort::<_, _, true>(x, comparator)
+        } else {
+            insertion_sort::<_, _, false>(x, comparator)
+        };
+        if swapped {
+            ctx.mark_swapped();
+        }
+        return;
+    }
     if x.len() > 1 {
-        let mid_point = x.len() / 2;
-//        let first = &mut x[..mid_point];
-//        let second = &mut x[mid_point..];
+        // 要素数を2のべき乗に切り上げた長さの半分で分割する
+        // 2のべき乗でない場合、前半は常に満たされ、後半の末尾に仮想的な番兵が並ぶ
+        let mid_point = padded_mid_point(x.len());
         // xをmid_pointを境にした2つの可変の借用に分割し
         // firstとsecondに束縛する
         let (first, second) = x.split_at_mut(mid_point);
 
         // xの分割後の要素数をしきい値と比較する
-        if mid_point >= PARALLEL_THRESHOLD {
+        // 番兵が後半の末尾から動かないよう、前半を逆順・後半をforward順にソートする
+        if let Some(ctx) = ctx.parallel(mid_point) {
             // しきい値以上なら並列にソートする（並列処理）
-            rayon::join(|| do_sort(first, true, comparator),
-                        || do_sort(second, false, comparator)
-            );
+            trace!("sort: halves of len {} sorted in parallel", mid_point);
+            join(|| do_sort(first, !forward, comparator, &ctx),
+                 || do_sort(second, forward, comparator, &ctx));
+        } else {
+            // しきい値未満（または並列に処理できる段数を使い切った）なら順番にソートする（順次処理）
+            trace!("sort: halves of len {} sorted sequentially", mid_point);
+            do_sort(first, !forward, comparator, ctx);
+            do_sort(second, forward, comparator, ctx);
+        }
+        // デバッグビルドでは、マージの前にxがバイトニック列（前半と後半が逆向きにソート済み）に
+        // なっているかを確かめる。再帰を誤って変更した時に気づけるようにするためで、
+        // リリースビルドではこの確認はなくなる。中断された場合は途中の状態なので確かめない
+        // 利用者のcomparatorは全順序とは限らず（NaNを含むpartial_cmpなど）、その場合は
+        // バイトニック列にならなくても誤りではない。確認のための余分な呼び出しで
+        // comparatorの呼ばれる回数が変わらないよう、Ord::cmpでソートする時だけ確かめる
+        debug_assert!(
+            !ctx.total_order || ctx.is_cancelled() || is_bitonic_by(x, comparator),
+            "the input to the bitonic merge is not a bitonic sequence (x.len(): {})",
+            x.len()
+        );
+        // マージの向きはここで一度だけ決め、向きごとに単相化されたsub_sortを呼ぶ
+        trace!("merge start: len {}, forward {}", x.len(), forward);
+        if forward {
+            sub_sort::<_, _, true>(x, comparator, ctx);
         } else {
-            // しきい値未満なら順番にソートする（順次処理）
-            do_sort(&mut x[..mid_point], true, comparator);
-            do_sort(&mut x[mid_point..], false, comparator);
+            sub_sort::<_, _, false>(x, comparator, ctx);
         }
-        sub_sort(x, forward, comparator);
+        trace!("merge end: len {}, forward {}", x.len(), forward);
     }
 }
 
-pub fn sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
-    // do_sortを呼ぶ代わりにsort_byを呼ぶ
-    // is_power_of_twoはsort_byが呼ぶのでここからは削除
-    match *order {
-        SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
-        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+pub fn sort<T: Ord + Send>(x: &mut [T], order: SortOrder) -> Result<(), SortError> {
+    sort_with_threshold(x, order, PARALLEL_THRESHOLD)
+}
+
+// 並列に処理するかを決めるしきい値を指定してソートする
+// 0なら常に並列に、usize::MAXなら常に順番にソートする
+pub fn sort_with_threshold<T: Ord + Send>(
+    x: &mut [T],
+    order: SortOrder,
+    threshold: usize,
+) -> Result<(), SortError> {
+    check_depth(x.len())?;
+    // 比較は常にOrd::cmpで行い、降順はdo_sortのforwardをfalseにすることで得る
+    // comparatorは&dynではなく型引数Fとして渡されるので、do_sortはこのクロージャの型ごとに
+    // 単相化され、Ord::cmpの呼び出しはインライン展開される（間接呼び出しにはならない）
+    // そのためOrd専用の比較ネットワークは別に用意していない（benchesのcomparator_dispatchを参照）
+    let ctx = Context {
+        total_order: true,
+        ..Context::new(threshold)
+    };
+    do_sort(x, is_forward(order), &|a: &T, b: &T| a.cmp(b), &ctx);
+    Ok(())
+}
+
+// 型引数Dで指定した向きにソートする
+// 向きは型で決まるので、SortOrderの値によって分岐することはない
+pub fn sort_dir<D: Direction, T: Ord + Send>(x: &mut [T]) -> Result<(), SortError> {
+    check_depth(x.len())?;
+    // Directionは封印されているので、SWAP_CONDITIONはAscendingのGreaterかDescendingのLessだけ
+    let forward = D::SWAP_CONDITION == Ordering::Greater;
+    let ctx = Context {
+        total_order: true,
+        ..Context::new(PARALLEL_THRESHOLD)
+    };
+    do_sort(x, forward, &|a: &T, b: &T| a.cmp(b), &ctx);
+    Ok(())
+}
+
+// 昇順にソートする。sort_dir::<Ascending, _>と同じ
+pub fn sort_asc<T: Ord + Send>(x: &mut [T]) -> Result<(), SortError> {
+    sort_dir::<Ascending, _>(x)
+}
+
+// 降順にソートする。sort_dir::<Descending, _>と同じ
+pub fn sort_desc<T: Ord + Send>(x: &mut [T]) -> Result<(), SortError> {
+    sort_dir::<Descending, _>(x)
+}
+
+// Ordを実装した型を、orderで指定した順にソートする
+// sortと同じく、比較にはOrd::cmpが使われる
+pub fn sort_ordered<T: Ord + Send>(x: &mut [T], order: SortOrder) -> Result<(), SortError> {
+    sort_by_order(x, order, &|a, b| a.cmp(b))
+}
+
+// orderをdo_sortのforwardに変換する
+// 降順は比較の引数を入れ替えるのではなく、ソートの向きを反転させることで実現する
+// 昇順にソートしてからutils::reverse_in_placeで逆順にする方法も試したが、
+// 向きはコンパイル時に決まるので降順のソートも昇順と同じ速さで、
+// 2^16〜2^22要素では実行時間に差はなかった（逆順にする分だけ遅くなることもある）
+fn is_forward(order: SortOrder) -> bool {
+    match order {
+        SortOrder::Ascending => true,
+        SortOrder::Descending => false,
     }
 }
 
-fn sub_sort<T, F>(x: &mut [T], forward: bool, compartor: &F)
+// FORWARDはマージの向き。コンパイル時に決まる定数にすることで、
+// 昇順用と降順用の内側のループがそれぞれ別の関数として生成される
+fn sub_sort<T, F, const FORWARD: bool>(x: &mut [T], compartor: &F, ctx: &Context)
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
-    if x.len() > 1 {
-        compare_and_swap(x, forward, compartor);
-        let mid_point = x.len() / 2;
+    if x.len() > 1 && !ctx.is_cancelled() {
+        compare_and_swap::<_, _, FORWARD>(x, compartor, ctx);
+        let mid_point = padded_mid_point(x.len());
         let (first, second) = x.split_at_mut(mid_point);
 
-        if mid_point >= PARALLEL_THRESHOLD {
-            rayon::join(|| sub_sort(first, forward, compartor),
-                        || sub_sort(second, forward, compartor));
+        if let Some(ctx) = ctx.parallel(mid_point) {
+            trace!("sub_sort: halves of len {} merged in parallel", mid_point);
+            join(|| sub_sort::<_, _, FORWARD>(first, compartor, &ctx),
+                 || sub_sort::<_, _, FORWARD>(second, compartor, &ctx));
         } else {
-            sub_sort(first, forward, compartor);
-            sub_sort(second, forward, compartor);
+            trace!("sub_sort: halves of len {} merged sequentially", mid_point);
+            sub_sort::<_, _, FORWARD>(first, compartor, ctx);
+            sub_sort::<_, _, FORWARD>(second, compartor, ctx);
+        }
+    }
+}
+
+fn compare_and_swap<T, F, const FORWARD: bool>(x: &mut [T], comparator: &F, ctx: &Context)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let mid_point = padded_mid_point(x.len());
+    let comparisons = x.len() - mid_point;
+    // 前半と後半に分けてzipでたどることで、添字の範囲チェックを省く
+    // 相手が番兵（x.len()以降の仮想的な要素）になる比較は交換が起こらないので、
+    // 後半の要素数（comparisons）だけ比較すればよい
+    let (first, second) = x.split_at_mut(mid_point);
+    let (swaps, equals) = if ctx.parallel_compare(comparisons) {
+        trace!("compare_and_swap: {} pairs compared in parallel", comparisons);
+        compare_and_swap_parallel::<_, _, FORWARD>(first, second, comparator)
+    } else {
+        trace!("compare_and_swap: {} pairs compared sequentially", comparisons);
+        let mut swaps = 0;
+        let mut equals = 0;
+        for (a, b) in first.iter_mut().zip(second.iter_mut()) {
+            let (swapped, equal) = compare_pair::<_, _, FORWARD>(a, b, comparator);
+            swaps += swapped;
+            equals += equal;
         }
+        (swaps, equals)
+    };
+
+    if swaps > 0 {
+        ctx.mark_swapped();
+    }
+    // カウンタへの加算は、アトミック操作を減らすため呼び出しごとにまとめて行う
+    if let Some(counters) = ctx.counters {
+        counters.comparisons.fetch_add(comparisons, AtomicOrdering::Relaxed);
+        counters.swaps.fetch_add(swaps, AtomicOrdering::Relaxed);
+        counters.equals.fetch_add(equals, AtomicOrdering::Relaxed);
     }
 }
 
-fn compare_and_swap<T, F>(x: &mut [T], forward: bool, comparator: &F)
+// aとbをcomparatorで比較し、FORWARDの向きに並んでいなければ交換する
+// 交換したか、比較の結果がEqualだったかを、それぞれ0か1で返す
+#[inline]
+fn compare_pair<T, F, const FORWARD: bool>(a: &mut T, b: &mut T, comparator: &F) -> (usize, usize)
 where
     F: Fn(&T, &T) -> Ordering,
 {
-    // 比較に先立ち、forward(bool)をOrderingに変換する
-    let swap_condition = if forward {
+    // FORWARDをOrderingに変換する。定数なのでコンパイル時に決まる
+    let swap_condition = if FORWARD {
         Ordering::Greater
     } else {
         Ordering::Less
     };
-    let mid_point = x.len() / 2;
-    for i in 0..mid_point {
-        // comparatorクロージャで2要素を比較し、返されたOrderingのバリアントが
-        // swap_conditionと等しいなら要素を交換する
-        if comparator(&x[i], &x[mid_point + i]) == swap_condition {
-            x.swap(i, mid_point + i)
-        }
+    // comparatorクロージャで2要素を比較し、返されたOrderingのバリアントが
+    // swap_conditionと等しいなら要素を交換する。Equalなら交換しない
+    let ordering = comparator(a, b);
+    if ordering == swap_condition {
+        core::mem::swap(a, b);
+        (1, 0)
+    } else if ordering == Ordering::Equal {
+        (0, 1)
+    } else {
+        (0, 0)
     }
 }
 
-pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), String>
+// compare_and_swapのループをrayonの並列イテレータで処理する。交換と等しかった回数を返す
+// firstとsecondはsplit_at_mutで得た重ならない区間なので、par_iter_mutで各組を
+// 別々のスレッドに渡しても、同じ要素を2つのスレッドが書き換えることはない
+#[cfg(feature = "parallel")]
+fn compare_and_swap_parallel<T, F, const FORWARD: bool>(
+    first: &mut [T],
+    second: &mut [T],
+    comparator: &F,
+) -> (usize, usize)
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
-    if x.len().is_power_of_two() {
-        do_sort(x, true, comparator);
-        Ok(())
+    use rayon::prelude::*;
+
+    first
+        .par_iter_mut()
+        .zip(second.par_iter_mut())
+        .map(|(a, b)| compare_pair::<_, _, FORWARD>(a, b, comparator))
+        .reduce(|| (0, 0), |x, y| (x.0 + y.0, x.1 + y.1))
+}
+
+// rayonがない場合、Context::parallel_compareは常にfalseを返すので呼ばれない
+#[cfg(not(feature = "parallel"))]
+fn compare_and_swap_parallel<T, F, const FORWARD: bool>(
+    _first: &mut [T],
+    _second: &mut [T],
+    _comparator: &F,
+) -> (usize, usize)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    unreachable!()
+}
+
+// do_sortの再帰の深さの上限（要素数でいえば2の40乗）
+// 実際にはこれほど大きなスライスはまず扱わないので、十分に余裕のある値にしている
+const MAX_RECURSION_DEPTH: u32 = 40;
+
+// 要素数lenのスライスをソートする時のdo_sortの再帰の深さを返す（log2(len)を切り上げた値）
+// sub_sortの再帰も同じ深さになる
+fn recursion_depth(len: usize) -> u32 {
+    if len <= 1 {
+        0
     } else {
-        Err(format!(
-            "The length of x is not a power of two. (x.len(): {})",
-            x.len()
-        ))
+        usize::BITS - (len - 1).leading_zeros()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    // 親モジュール(first)のsort関数を使用する
-    use super::{sort, sort_by};
-    use crate::SortOrder::*;
-    use crate::utils::{new_u32_vec, is_sorted_ascending, is_sorted_descending};
+// 再帰が深くなりすぎてスタックが溢れないよう、ソートを始める前に要素数を確認する
+// 同じ再帰の構造を持つsimdモジュールのソートでも使う
+pub(crate) fn check_depth(len: usize) -> Result<(), SortError> {
+    if recursion_depth(len) > MAX_RECURSION_DEPTH {
+        Err(SortError::TooLarge { len })
+    } else {
+        Ok(())
+    }
+}
 
-    // #[test]のついた関数はcargo testとした時に実行される
-    #[test]
-    fn sort_u32_ascending() {
-        // テストデータとしてu32型のベクタを作成しxに束縛する
-        // sort関数によって内容が更新されるので、可変を表すmutが必要
-        let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+// xの要素数を2のべき乗に切り上げたときの中央の位置を返す
+// 2のべき乗ならx.len() / 2と同じ
+fn padded_mid_point(len: usize) -> usize {
+    next_power_of_two_len(len) / 2
+}
 
-        // xのスライスを作成し、sort関数を呼び出す
-        // &mut x は&mut x[..]と書いてもいい
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+// xの要素数が2のべき乗でない場合は、次の2のべき乗まで番兵でパディングしたものとしてソートする
+// 番兵はcomparatorにおける最大の要素（降順に並べるDescendingのsortなどでは最小の要素）として扱われ、
+// 常に配列の末尾（x.len()以降）に留まるため、実際にメモリを確保したり
+// 結果に番兵が現れたりすることはない
+// そのためTにCloneやSentinelは必要なく、パディング用の作業領域を呼び出し側が用意する必要もない
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    sort_unstable_by(x, comparator)
+}
 
-        // xの要素が昇順にソートされていることを確認する
-        assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
-    }
+/// comparatorの順序でソートする。処理はsort_byと同じ
+///
+/// バイトニックソートは安定ではないので、comparatorで等しいとされた要素の順序は
+/// 保たれないことがある。標準ライブラリのslice::sort_unstable_byに合わせた名前で、
+/// そのことを明示するために用意している。順序を保ちたい時はsort_by_stableを使う
+///
+/// 16要素以下の区間は挿入ソートで並べるため、小さなスライスでは順序が保たれるが、
+/// それを当てにしてはいけない
+///
+/// ```
+/// use bitonic_sorter::fourth::sort_unstable_by;
+///
+/// // 先頭と末尾の要素だけキーが1で、残りの18要素はキーが0
+/// let mut x: Vec<(u32, u32)> = (0..20).map(|i| (u32::from(i == 0 || i == 19), i)).collect();
+/// sort_unstable_by(&mut x, &|a: &(u32, u32), b: &(u32, u32)| a.0.cmp(&b.0)).unwrap();
+/// // キーの順には並ぶ
+/// assert!(x[..18].iter().all(|r| r.0 == 0) && x[18..].iter().all(|r| r.0 == 1));
+/// // キーが0の要素は、元の順序（2番目の値の昇順）のままとは限らない
+/// assert!(x[..18].windows(2).any(|w| w[0].1 > w[1].1));
+/// ```
+pub fn sort_unstable_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    sort_by_with_threshold(x, comparator, PARALLEL_THRESHOLD)
+}
 
-    #[test]
-    fn sort_u32_descending() {
-        let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
-        // xの要素が降順にソートされていることを確認する
-        assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
-    }
+// 並列に処理するかを決めるしきい値を指定してソートする
+// 分割後の要素数がthreshold以上ならrayon::joinで並列に処理する
+// 並列に処理される区間同士は重ならず、比較と交換の組み合わせはデータにもしきい値にもよらないので、
+// 等しい要素の並びも含めて、結果はしきい値やスレッドの実行順に関わらず常に同じになる
+pub fn sort_by_with_threshold<T, F>(
+    x: &mut [T],
+    comparator: &F,
+    threshold: usize,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    BitonicConfig::new().threshold(threshold).sort_unstable_by(x, comparator)
+}
 
-    #[test]
-    fn sort_str_ascending() {
-        // 文字列のベクタを作りソートする
-        let mut x = vec![
-            "Rust",
-            "is",
-            "fast",
-            "and",
-            "memory-efficient",
-            "with",
-            "no",
-            "GC",
-        ];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
-        assert_eq!(
-            x,
-            vec![
-                "GC",
-                "Rust",
-                "and",
-                "fast",
-                "is",
-                "memory-efficient",
-                "no",
-                "with"
-            ]
-        );
-    }
+// 並列に処理する再帰の段数をmax_parallel_depthまでに制限してソートする
+// 要素数がしきい値以上でも、それより深い再帰は順番に処理するので、同時に実行される
+// タスクは2^max_parallel_depth個までになる。0なら並列には処理しない
+// 指定しない場合、rayonでは制限せず、scopedフィーチャーではlog2(CPUの数)段までにする
+pub fn sort_by_with_parallel_depth<T, F>(
+    x: &mut [T],
+    comparator: &F,
+    max_parallel_depth: usize,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    BitonicConfig::new()
+        .parallel_depth(max_parallel_depth)
+        .sort_unstable_by(x, comparator)
+}
 
-    #[test]
-    fn sort_str_descending() {
-        let mut x = vec![
-            "Rust",
-            "is",
-            "fast",
-            "and",
-            "memory-efficient",
-            "with",
-            "no",
-            "GC",
-        ];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
-        assert_eq!(
-            x,
-            vec![
-                "with",
-                "no",
-                "memory-efficient",
-                "is",
-                "fast",
-                "and",
-                "Rust",
-                "GC"
-            ]
-        );
-    }
+// comparatorの順序で並べるか、その逆順で並べるかをorderで指定してソートする
+// comparatorの引数を入れ替えなくても降順の結果が得られる
+pub fn sort_by_order<T, F>(
+    x: &mut [T],
+    order: SortOrder,
+    comparator: &F,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    check_depth(x.len())?;
+    // Descendingの時はdo_sortのforwardをfalseにして最終的な向きを反転させる
+    do_sort(x, is_forward(order), comparator, &Context::new(PARALLEL_THRESHOLD));
+    Ok(())
+}
 
-    #[test]
-    fn sort_to_fail() {
-        let mut x = vec![10, 30, 11]; // x.len()が2のべき乗になっていない
-        assert!(sort(&mut x, &Ascending).is_err()); // 戻り値はErr
+// 要素数nのスライスをバイトニックソートする時の比較の回数を返す
+// nが2のべき乗なら(n / 4) * log2(n) * (log2(n) + 1)、そうでなければ0を返す
+// const fnなので、計測用のバッファの大きさを決めるなど、定数の文脈でも使える
+pub const fn comparator_count(n: usize) -> usize {
+    if !n.is_power_of_two() {
+        return 0;
     }
+    let log_n = n.trailing_zeros() as usize;
+    // (n / 4)を先に計算するとn = 2で0になってしまうので、n / 2とlog2(n) * (log2(n) + 1) / 2に
+    // 分ける（log2(n) * (log2(n) + 1)は常に偶数なので割り切れる）
+    (n / 2) * (log_n * (log_n + 1) / 2)
+}
 
-    // 構造体Studentを定義する
-    // 構造体は関連する値を一つにまとめたデータ構造。複数のデータフィールドを持つ
-    // deriveアトリビュートを使い、DebugトレイトとPartialEqトレイトを自動導出する
-    #[derive(Debug, PartialEq)]
-    struct Student {
-        first_name: String, // first_bame(名前)フィールド。String型
-        last_name: String,  // last_name(名字)フィールド。String型
-        age: u8,            // age(年齢)フィールド。u8型(8ビット符号なし整数)
+// ソートしながら比較と交換の回数を数え、SortStatsとして返す
+// 比較の結果がEqualだった回数も数えるので、常にEqualを返すような誤ったcomparatorを見つけられる
+// 要素数nが2のべき乗なら、比較回数は常に(n / 4) * log2(n) * (log2(n) + 1)になる
+// 回数を数える間は、小さな区間も挿入ソートを使わずにバイトニックソートで並べる
+pub fn sort_by_counted<T, F>(x: &mut [T], comparator: &F) -> Result<SortStats, SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    check_depth(x.len())?;
+    let counters = Counters::default();
+    let ctx = Context {
+        counters: Some(&counters),
+        ..Context::new(PARALLEL_THRESHOLD)
+    };
+    do_sort(x, true, comparator, &ctx);
+    Ok(counters.stats())
+}
+
+// ソートし、要素の交換が1回でも起きたらtrueを返す。falseならxは元からソート済みだった
+// 交換が起きたかはContextのフラグで受け取るので、sort_byと同じ比較で済む
+pub fn sort_by_reporting<T, F>(x: &mut [T], comparator: &F) -> Result<bool, SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    // バイトニックソートは後半をいったん降順に並べ替えるので、ソート済みの入力でも交換が起きる
+    // そのため、ソート済みなら交換せずにすぐfalseを返す
+    check_depth(x.len())?;
+    if is_sorted_by(x, comparator) {
+        return Ok(false);
     }
+    // 交換の回数は数えず、交換が起きたかだけをフラグで受け取る
+    // sort_by_countedと違い、小さな区間は挿入ソートで並べたままにできる
+    let swapped = AtomicBool::new(false);
+    let ctx = Context {
+        swapped: Some(&swapped),
+        ..Context::new(PARALLEL_THRESHOLD)
+    };
+    do_sort(x, true, comparator, &ctx);
+    Ok(swapped.load(AtomicOrdering::Relaxed))
+}
 
-    // implブロックを使うと対象の型に関連関数やメソッドを実装できる
-    impl Student {
-        // 構造体Studentを初期化して返す。Selfはimpl対象の型（Student）の別名
-        fn new(first_name: &str, last_name: &str, age: u8) -> Self {
-            Self {
-                // to_stringメソッドで&str型の引数からString型の値を作る。詳しくは5章で説明
-                first_name: first_name.to_string(),
-                last_name: last_name.to_string(),
-                age, // フィールドと変数が同じ名前なら省略形で書ける（JSみたいに
-            }
-        }
+// should_sortにxを渡し、trueを返した時だけソートする。ソートしたかどうかを返す
+// falseを返した場合、xは変更されない
+// 要素数が少ない時や、業務上の理由でソート済みとわかっている時にソートを省くのに使う
+pub fn sort_by_if<T, F, P>(x: &mut [T], comparator: &F, should_sort: P) -> Result<bool, SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    P: FnOnce(&[T]) -> bool,
+{
+    if !should_sort(x) {
+        return Ok(false);
     }
+    sort_by(x, comparator).map(|()| true)
+}
 
-    //    impl PartialEq for Student {
-    //        fn eq(&self, other: &self) -> bool {
-    //             selfとotherですべてのフィールド同士を比較して、
-    //             どのフィールドも等しいならselfとotherは等しい
+// Option<Ordering>を返す比較関数（PartialOrd::partial_cmpなど）でソートする
+// fがNoneを返した組は等しいものとして扱う。そのため、NaNを含むf64のように比較できない値が
+// あると結果は規定しない。fがすべての組で比較できることは、呼び出し側が保証すること
+pub fn sort_by_partial<T, F>(x: &mut [T], f: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Option<Ordering>,
+{
+    sort_by(x, &|a, b| f(a, b).unwrap_or(Ordering::Equal))
+}
+
+// f64はOrdを実装していないのでsortは使えない。代わりにf64::total_cmpの全順序でソートする
+// 昇順なら-0.0は+0.0より前に、NaNは末尾にまとめられる
+// （符号ビットが立ったNaNだけは、total_cmpの定義に従い先頭に置かれる）
+pub fn sort_by_float_total(x: &mut [f64], order: SortOrder) -> Result<(), SortError> {
+    sort_by_order(x, order, &|a, b| a.total_cmp(b))
+}
+
+// 再帰を使わずに、kとjの2重ループで比較と交換を行うバイトニックソート
+// 常に順番に処理され、スタックの深さがxの要素数に依存しないので、組み込み環境などで使える
+// 各段の最初の比較で区間を折り返して比べる（i ^ (k - 1)）ため、すべての比較が同じ向きになり、
+// 番兵（x.len()以降の仮想的な要素）は動かない
+pub fn sort_iterative<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    // 処理はsort_by_mutと同じ
+    sort_by_mut(x, &mut |a, b| comparator(a, b))
+}
+
+// 状態を変更するFnMutのcomparatorでソートする（比較の回数を数える、キャッシュするなど）
+// comparatorを複数のスレッドから同時に呼べないので、並列には処理せず常に1つのスレッドで処理する
+// 比較の順序はsort_iterativeと同じ
+pub fn sort_by_mut<T, F>(x: &mut [T], comparator: &mut F) -> Result<(), SortError>
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let len = x.len();
+    let padded_len = next_power_of_two_len(len);
+    // kはマージする区間の長さ、maskは比較相手の位置（i ^ mask）を求めるためのビットマスク
+    let mut k = 2;
+    while k <= padded_len {
+        let mut mask = k - 1;
+        while mask > 0 {
+            for i in 0..len {
+                let l = i ^ mask;
+                if l > i && l < len && comparator(&x[i], &x[l]) == Ordering::Greater {
+                    x.swap(i, l);
+                }
+            }
+            // 折り返しの比較の後は、距離k / 4、k / 8、…、1の比較を行う
+            mask = if mask == k - 1 { k / 4 } else { mask / 2 };
+        }
+        k *= 2;
+    }
+    Ok(())
+}
+
+// xのうちrangeの範囲だけをソートし、範囲の外の要素には触れない
+// 範囲がxに収まっていない（startがendより大きい、またはendがx.len()より大きい）場合は
+// パニックせずにInvalidRangeを返す。範囲の要素数は2のべき乗でなくてもよい
+pub fn sort_range_by<T, F>(
+    x: &mut [T],
+    range: Range<usize>,
+    comparator: &F,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if range.start > range.end || range.end > x.len() {
+        return Err(SortError::InvalidRange {
+            len: x.len(),
+            start: range.start,
+            end: range.end,
+        });
+    }
+    sort_by(&mut x[range], comparator)
+}
+
+// 先頭のvalid_len要素だけが有効なxをソートする。valid_len以降の要素はどんな要素より
+// 大きいものとして扱われ、末尾に留まる（中身は比較されず、並びも変わらない）
+// 容量が2のべき乗の固定長のバッファを、確保し直さずに一部だけ使う時などに使う
+pub fn sort_valid_by<T, F>(x: &mut [T], valid_len: usize, comparator: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if valid_len > x.len() {
+        return Err(SortError::ValidLenOutOfRange {
+            len: x.len(),
+            valid_len,
+        });
+    }
+    // 末尾の無効な要素は、仮想的な番兵と同じく常に最大として扱われるので、
+    // 有効な区間だけを番兵でパディングしてソートした場合と結果は変わらない
+    sort_by(&mut x[..valid_len], comparator)
+}
+
+// 要素数以下で最大の2のべき乗をpとして、先頭のp要素だけをソートしてpを返す
+// 残りの要素には触れないので、パディングせずに2のべき乗の長さだけ処理したい
+// ストリーミング用のバッファなどに使う。空のスライスなら何もせず0を返す
+pub fn sort_prefix_by<T, F>(x: &mut [T], comparator: &F) -> Result<usize, SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if x.is_empty() {
+        return Ok(0);
+    }
+    // 最上位の1のビットだけを残すと、len以下で最大の2のべき乗になる
+    let p = 1 << (usize::BITS - 1 - x.len().leading_zeros());
+    sort_by(&mut x[..p], comparator)?;
+    Ok(p)
+}
+
+// 先頭のk要素が、comparatorの順序で小さい方からk個の要素をソートしたものになるよう並べ替える
+// k番目より後ろの要素の並びは規定しない
+// kを2のべき乗に切り上げた長さmの区間ごとにソートし、先頭の区間と小さい方を入れ替えては
+// バイトニックマージすることで、全体をソートするより少ない比較回数で済ませる
+pub fn partial_sort_by<T, F>(x: &mut [T], k: usize, comparator: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if k == 0 {
+        return Ok(());
+    }
+    // kがx.len()以上なら全体をソートする。2のべき乗に切り上げるとあふれるような
+    // 大きなkでもパニックしないよう、切り上げる前に確かめる
+    if k >= x.len() {
+        return sort_by(x, comparator);
+    }
+    let m = k.next_power_of_two();
+    if m >= x.len() {
+        // 先頭の区間がx全体を覆うなら、全体をソートするのと変わらない
+        return sort_by(x, comparator);
+    }
+
+    let ctx = Context::new(PARALLEL_THRESHOLD);
+    let (head, tail) = x.split_at_mut(m);
+    do_sort(head, true, comparator, &ctx);
+    for chunk in tail.chunks_mut(m) {
+        do_sort(chunk, true, comparator, &ctx);
+        // headの後ろとchunkの前を折り返して比べ、小さい方をheadに集める
+        // headは昇順の後に降順が続くバイトニック列になり、小さい方からm個の要素を含む
+        for (i, y) in chunk.iter_mut().enumerate() {
+            if comparator(&head[m - 1 - i], y) == Ordering::Greater {
+                core::mem::swap(&mut head[m - 1 - i], y);
+            }
+        }
+        sub_sort::<_, _, true>(head, comparator, &ctx);
+    }
+    Ok(())
+}
+
+// ソートの進み具合をprogressに知らせながらソートする
+// progressは(完了した段数, 全体の段数)を引数に、バイトニックソートの各段が終わるたびに呼ばれる
+// 要素数を2のべき乗に切り上げた長さをnとすると、全体の段数はlog2(n) * (log2(n) + 1) / 2
+// 各段は並列に処理されるが、progressは段の処理がすべて終わった後に呼び出し元のスレッドで呼ばれる
+pub fn sort_by_with_progress<T, F, P>(
+    x: &mut [T],
+    comparator: &F,
+    mut progress: P,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    P: FnMut(usize, usize),
+{
+    sort_by_stages(x, comparator, PARALLEL_THRESHOLD, |_, completed_stages, total_stages| {
+        progress(completed_stages, total_stages)
+    });
+    Ok(())
+}
+
+// バイトニックソートの各段が終わるたびに、その時点のxの複製を取りながらソートする
+// 返されるベクタのi番目は、i + 1段目が終わった後のxで、最後の要素はソート済みのxと同じになる
+// バイトニックソートの仕組みを説明するためのもので、段の数だけxを複製するので大きなxには向かない
+// 複製の間に他のスレッドがxを書き換えることのないよう、並列には処理しない
+pub fn sort_by_trace<T, F>(x: &mut [T], comparator: &F) -> Result<Vec<Vec<T>>, SortError>
+where
+    T: Clone + Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let mut snapshots = Vec::new();
+    sort_by_stages(x, comparator, usize::MAX, |x, _, _| snapshots.push(x.to_vec()));
+    Ok(snapshots)
+}
+
+// sort_iterativeと同じく、段ごとにx全体の比較と交換を行う
+// 各段が終わるたびに、on_stageを(x, 完了した段数, 全体の段数)を引数にして呼び出す
+fn sort_by_stages<T, F, S>(x: &mut [T], comparator: &F, threshold: usize, mut on_stage: S)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    S: FnMut(&[T], usize, usize),
+{
+    let padded_len = next_power_of_two_len(x.len());
+    let log_n = padded_len.trailing_zeros() as usize;
+    let total_stages = log_n * (log_n + 1) / 2;
+    let mut completed_stages = 0;
+    // 各段の並列処理もdo_sortと同じく、しきい値と並列に処理する段数の既定値に従う
+    let ctx = Context::new(threshold);
+
+    let mut k = 2;
+    while k <= padded_len {
+        // 最初の段は長さkの区間を折り返して比べ、残りの段は長さk / 2、k / 4、…、2の区間を比べる
+        do_stage(x, k, true, comparator, &ctx);
+        completed_stages += 1;
+        on_stage(x, completed_stages, total_stages);
+
+        let mut block = k / 2;
+        while block > 1 {
+            do_stage(x, block, false, comparator, &ctx);
+            completed_stages += 1;
+            on_stage(x, completed_stages, total_stages);
+            block /= 2;
+        }
+        k *= 2;
+    }
+}
+
+// 長さblockの区間ごとに、前半の要素と後半の要素を比べて交換する
+// flipがtrueなら区間を折り返して（i番目とblock - 1 - i番目を）比べ、
+// falseなら前半のi番目と後半のi番目を比べる
+fn do_stage<T, F>(x: &mut [T], block: usize, flip: bool, comparator: &F, ctx: &Context)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if x.len() > block {
+        // 複数の区間を含むなら、区間の境界で2つに分割する
+        let mid_point = padded_mid_point(x.len());
+        let (first, second) = x.split_at_mut(mid_point);
+        if let Some(ctx) = ctx.parallel(mid_point) {
+            join(|| do_stage(first, block, flip, comparator, &ctx),
+                 || do_stage(second, block, flip, comparator, &ctx));
+        } else {
+            do_stage(first, block, flip, comparator, ctx);
+            do_stage(second, block, flip, comparator, ctx);
+        }
+    } else {
+        let half = block / 2;
+        for i in 0..half {
+            let j = if flip { block - 1 - i } else { half + i };
+            // 相手が番兵なら交換は起こらない
+            if j < x.len() && comparator(&x[i], &x[j]) == Ordering::Greater {
+                x.swap(i, j);
+            }
+        }
+    }
+}
+
+// cancelがtrueになったら途中で中断できるソート
+// 中断された場合はSortError::Cancelledを返す。その時のxは途中まで並べ替えられた状態になる
+pub fn sort_by_cancellable<T, F>(
+    x: &mut [T],
+    comparator: &F,
+    cancel: &AtomicBool,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    check_depth(x.len())?;
+    let ctx = Context {
+        cancel: Some(cancel),
+        ..Context::new(PARALLEL_THRESHOLD)
+    };
+    do_sort(x, true, comparator, &ctx);
+    if ctx.is_cancelled() {
+        Err(SortError::Cancelled)
+    } else {
+        Ok(())
+    }
+}
+
+// 比較をyield_every回行うたびにyield_nowを呼び出しながら、順番にソートする
+// yield_nowで他のタスクに制御を譲ったり進捗を知らせたりすれば、大きなソートの間も
+// 呼び出し元のスレッドを長く占有せずに済む。yield_everyが0ならyield_nowは呼ばない
+// 並列には処理しないので、yield_nowは常に呼び出し元のスレッドで呼ばれる
+// そのため、シングルスレッドの実行環境で使うのに向いている
+// yield_nowは同期的な関数なので、asyncの実行器に制御を返すには、
+// 実行器が用意する仕組み（tokioのblock_in_placeなど）と組み合わせる必要がある
+pub fn sort_by_yielding<T, F, Y>(
+    x: &mut [T],
+    comparator: &F,
+    yield_every: usize,
+    yield_now: Y,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+    Y: Sync + Fn(),
+{
+    let comparisons = AtomicUsize::new(0);
+    let yielding = |a: &T, b: &T| {
+        let n = comparisons.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+        if yield_every > 0 && n % yield_every == 0 {
+            yield_now();
+        }
+        comparator(a, b)
+    };
+    sort_by_with_threshold(x, &yielding, usize::MAX)
+}
+
+// 失敗することがある比較関数でソートする（stdフィーチャーが必要）
+// comparatorが最初に返したエラーをTrySortError::Comparatorとして返す
+// エラーが返されたら、それ以降の比較はcomparatorを呼ばずに中断する
+// 並列に処理中の比較は止められないので、その分はcomparatorが呼ばれることがある
+// 失敗した時のxは、要素の並びは規定しないが、元の要素をすべて含んだ状態になる
+#[cfg(feature = "std")]
+pub fn try_sort_by<T, F, E>(x: &mut [T], comparator: &F) -> Result<(), TrySortError<E>>
+where
+    T: Send,
+    E: Send,
+    F: Sync + Fn(&T, &T) -> Result<Ordering, E>,
+{
+    use std::sync::Mutex;
+
+    check_depth(x.len())?;
+
+    // 最初のエラーを格納する場所。複数のスレッドから書き込まれうるのでMutexで守る
+    let first_error: Mutex<Option<E>> = Mutex::new(None);
+    let failed = AtomicBool::new(false);
+    let ctx = Context {
+        cancel: Some(&failed),
+        ..Context::new(PARALLEL_THRESHOLD)
+    };
+    let infallible = |a: &T, b: &T| {
+        // すでに失敗していたらcomparatorを呼ばない。Equalなら交換は起こらない
+        if failed.load(AtomicOrdering::Relaxed) {
+            return Ordering::Equal;
+        }
+        comparator(a, b).unwrap_or_else(|e| {
+            let mut slot = first_error.lock().unwrap();
+            if slot.is_none() {
+                *slot = Some(e);
+            }
+            failed.store(true, AtomicOrdering::Relaxed);
+            Ordering::Equal
+        })
+    };
+    do_sort(x, true, &infallible, &ctx);
+
+    match first_error.into_inner().unwrap() {
+        Some(e) => Err(TrySortError::Comparator(e)),
+        None => Ok(()),
+    }
+}
+
+// xを変更せずに、ソートした結果を新しいベクタとして返す
+pub fn sorted_by<T, F>(x: &[T], comparator: &F) -> Result<Vec<T>, SortError>
+where
+    T: Clone + Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    // to_vecでxの要素を複製したベクタを作り、それをソートする
+    let mut v = x.to_vec();
+    sort_by(&mut v, comparator)?;
+    Ok(v)
+}
+
+// xを変更せずに、comparatorの順序で中央値となる要素の複製を返す
+// 要素数が偶数の場合は、中央の2つのうち前の方（小さい方）を返す
+// 今はxの複製全体をソートしているが、中央値に関係しない比較を省けば速くできる
+// xが空ならSortError::Emptyを返す
+pub fn median_by<T, F>(x: &[T], comparator: &F) -> Result<T, SortError>
+where
+    T: Clone + Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if x.is_empty() {
+        return Err(SortError::Empty);
+    }
+    let mut v = sorted_by(x, comparator)?;
+    Ok(v.swap_remove((x.len() - 1) / 2))
+}
+
+// xを変更せずに、orderで指定した順にソートした結果を新しいベクタとして返す
+pub fn sorted<T: Ord + Clone + Send>(x: &[T], order: SortOrder) -> Result<Vec<T>, SortError> {
+    let mut v = x.to_vec();
+    sort(&mut v, order)?;
+    Ok(v)
+}
+
+// xを変更せずに、昇順と降順にソートした2つのベクタを返す
+// ソートは昇順の1回だけで、降順の結果はそれを逆順にしたものなので、2つは必ず互いの逆順になる
+pub fn sort_both<T: Ord + Clone + Send>(x: &[T]) -> Result<(Vec<T>, Vec<T>), SortError> {
+    let ascending = sorted(x, SortOrder::Ascending)?;
+    let mut descending = ascending.clone();
+    descending.reverse();
+    Ok((ascending, descending))
+}
+
+// Vec<T>をそのまま受け取ってソートする。処理はスライス版のsortに任せる
+// Vecを受け取ること自体が目的なので、スライスを勧めるclippyの警告は抑制する
+#[allow(clippy::ptr_arg)]
+pub fn sort_vec<T: Ord + Send>(x: &mut Vec<T>, order: SortOrder) -> Result<(), SortError> {
+    sort(x, order)
+}
+
+// orderで指定した順にソートしてから、連続する重複した要素を取り除く
+// ソート済みなので重複した要素は隣り合い、xは重複のない要素だけに切り詰められる
+pub fn sort_dedup<T: Ord + Send>(x: &mut Vec<T>, order: SortOrder) -> Result<(), SortError> {
+    sort(x, order)?;
+    x.dedup();
+    Ok(())
+}
+
+// イテレータから値を集めてベクタを作り、それをソートして返す
+pub fn sort_into_vec<T, I, F>(iter: I, comparator: &F) -> Result<Vec<T>, SortError>
+where
+    T: Send,
+    I: IntoIterator<Item = T>,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let mut v: Vec<T> = iter.into_iter().collect();
+    sort_by(&mut v, comparator)?;
+    Ok(v)
+}
+
+// 配列の要素数Nが2のべき乗であることをコンパイル時に確認するための型
+struct AssertPowerOfTwo<const N: usize>;
+
+impl<const N: usize> AssertPowerOfTwo<N> {
+    const OK: () = assert!(N.is_power_of_two(), "the length of the array is not a power of two");
+}
+
+/// 固定長の配列をorderで指定した順にソートする
+///
+/// 要素数Nが2のべき乗であることはコンパイル時に確認されるので、実行時の確認はなく失敗もしない
+///
+/// ```compile_fail
+/// use bitonic_sorter::fourth::sort_array;
+/// use bitonic_sorter::SortOrder;
+///
+/// let mut x = [3, 1, 2]; // 要素数が2のべき乗ではないのでコンパイルエラーになる
+/// sort_array(&mut x, SortOrder::Ascending);
+/// ```
+pub fn sort_array<T: Ord + Send, const N: usize>(x: &mut [T; N], order: SortOrder) {
+    // 関連定数を参照することで、このNについてのassert!がコンパイル時に評価される
+    #[allow(clippy::let_unit_value)]
+    let () = AssertPowerOfTwo::<N>::OK;
+    let ctx = Context::new(PARALLEL_THRESHOLD);
+    do_sort(x, is_forward(order), &|a: &T, b: &T| a.cmp(b), &ctx);
+}
+
+// スライス（とVec）にバイトニックソートのメソッドを追加する拡張トレイト
+// 処理はそれぞれsortとsort_byに任せる。成功したらソートしたスライスを返すので、
+// x.bitonic_sort(Ascending)?.iter()のように続けて処理を書ける
+pub trait BitonicSortExt<T> {
+    fn bitonic_sort(&mut self, order: SortOrder) -> Result<&mut Self, SortError>
+    where
+        T: Ord;
+
+    fn bitonic_sort_by<F>(&mut self, comparator: &F) -> Result<&mut Self, SortError>
+    where
+        F: Sync + Fn(&T, &T) -> Ordering;
+}
+
+impl<T: Send> BitonicSortExt<T> for [T] {
+    fn bitonic_sort(&mut self, order: SortOrder) -> Result<&mut Self, SortError>
+    where
+        T: Ord,
+    {
+        sort(self, order)?;
+        Ok(self)
+    }
+
+    fn bitonic_sort_by<F>(&mut self, comparator: &F) -> Result<&mut Self, SortError>
+    where
+        F: Sync + Fn(&T, &T) -> Ordering,
+    {
+        sort_by(self, comparator)?;
+        Ok(self)
+    }
+}
+
+// 作業用のバッファを使い回しながら、繰り返しソートするための構造体
+// パディングは仮想的な番兵で行うのでsort_byはメモリを確保しないが、
+// ソートした複製を返すsorted_byは、呼び出しのたびにベクタを確保する代わりに作業用のバッファを使う
+pub struct BitonicSorter<T> {
+    scratch: Vec<T>, // 作業用のバッファ。必要な時だけ大きくなり、小さくはならない
+}
+
+impl<T: Clone + Send> BitonicSorter<T> {
+    pub fn new() -> Self {
+        Self {
+            scratch: Vec::new(),
+        }
+    }
+
+    // xをその場でソートする。作業用のバッファは使わない
+    // 要素数が2のべき乗でなくても仮想的な番兵でパディングするので、その場でのソートには
+    // 作業用の領域がいらない。sorted_byと同じ型で呼び出せるよう、メソッドとしても用意している
+    pub fn sort_by<F>(&mut self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where
+        F: Sync + Fn(&T, &T) -> Ordering,
+    {
+        sort_by(x, comparator)
+    }
+
+    // xを変更せずに、作業用のバッファにソートした結果を作って返す
+    // 返されたスライスは、次にこのBitonicSorterを使うまで有効
+    pub fn sorted_by<F>(&mut self, x: &[T], comparator: &F) -> Result<&[T], SortError>
+    where
+        F: Sync + Fn(&T, &T) -> Ordering,
+    {
+        // clearは容量を変えないので、以前より大きなxが来た時だけ確保し直される
+        self.scratch.clear();
+        self.scratch.extend_from_slice(x);
+        sort_by(&mut self.scratch, comparator)?;
+        Ok(&self.scratch)
+    }
+
+    // 作業用のバッファの容量を返す
+    pub fn capacity(&self) -> usize {
+        self.scratch.capacity()
+    }
+}
+
+impl<T: Clone + Send> Default for BitonicSorter<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 少しずつ届くブロックを、届くたびにソートしておき、最後に1つのソート済みのベクタにまとめる
+// ブロックの要素数は2のべき乗でなくてもよい（各ブロックは仮想的な番兵でパディングしてソートされる）
+pub struct BlockSorter<T, F> {
+    blocks: Vec<Vec<T>>, // ソート済みのブロック
+    comparator: F,
+}
+
+impl<T, F> BlockSorter<T, F>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    pub fn new(comparator: F) -> Self {
+        Self {
+            blocks: Vec::new(),
+            comparator,
+        }
+    }
+
+    // blockをcomparatorの順序でソートして取っておく
+    pub fn push_block(&mut self, mut block: Vec<T>) -> Result<(), SortError> {
+        sort_by(&mut block, &self.comparator)?;
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    // これまでに受け取ったすべての要素を、1つのソート済みのベクタにして返す
+    // 隣り合うブロックを2つずつバイトニックマージすることを、ブロックが1つになるまで繰り返す
+    pub fn finish(self) -> Result<Vec<T>, SortError> {
+        let mut blocks = self.blocks;
+        check_depth(blocks.iter().map(|b| b.len()).sum())?;
+        let ctx = Context::new(PARALLEL_THRESHOLD);
+        while blocks.len() > 1 {
+            let mut merged = Vec::with_capacity(blocks.len().div_ceil(2));
+            let mut iter = blocks.into_iter();
+            while let Some(mut first) = iter.next() {
+                if let Some(second) = iter.next() {
+                    // 前のブロックを降順にして後ろのブロックをつなげると、降順の後に昇順が続き、
+                    // その後に番兵（最大の要素）が並ぶバイトニック列になる
+                    first.reverse();
+                    first.extend(second);
+                    sub_sort::<_, _, true>(&mut first, &self.comparator, &ctx);
+                }
+                merged.push(first);
+            }
+            blocks = merged;
+        }
+        Ok(blocks.pop().unwrap_or_default())
+    }
+}
+
+// ソートの設定をまとめた構造体。セッターをつなげて設定し、sort_byでソートする
+// sort_by_with_thresholdやsort_by_stableなどの関数は、この設定を1つだけ変えたものと同じ
+#[derive(Clone, Copy, Debug)]
+pub struct BitonicConfig<'a> {
+    threshold: usize,      // 並列に処理するかを決めるしきい値
+    parallel_depth: usize, // 並列に処理する再帰の段数の上限
+    stable: bool,          // 等しい要素の順序を保つか
+    pad: bool,             // 要素数が2のべき乗でないスライスを、仮想的な番兵で埋めてソートするか
+    #[cfg(feature = "parallel")]
+    pool: Option<&'a rayon::ThreadPool>, // ソートに使うスレッドプール。Noneならグローバルなもの
+    _pool: PhantomData<&'a ()>,
+}
+
+impl<'a> BitonicConfig<'a> {
+    // sort_byと同じ設定（しきい値はPARALLEL_THRESHOLD、不安定、パディングあり）を返す
+    pub fn new() -> Self {
+        Self {
+            threshold: PARALLEL_THRESHOLD,
+            parallel_depth: default_parallel_depth(),
+            stable: false,
+            pad: true,
+            #[cfg(feature = "parallel")]
+            pool: None,
+            _pool: PhantomData,
+        }
+    }
+
+    // 並列に処理するかを決めるしきい値を設定する。usize::MAXなら順番に処理する
+    pub fn threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    // 並列に処理する再帰の段数の上限を設定する（sort_by_with_parallel_depthを参照）
+    pub fn parallel_depth(mut self, max_parallel_depth: usize) -> Self {
+        self.parallel_depth = max_parallel_depth;
+        self
+    }
+
+    // trueなら安定ソートにする（sort_by_stableを参照）
+    pub fn stable(mut self, stable: bool) -> Self {
+        self.stable = stable;
+        self
+    }
+
+    // falseなら、要素数が2のべき乗でない（2要素以上の）スライスはNotPowerOfTwoを返す
+    pub fn pad(mut self, pad: bool) -> Self {
+        self.pad = pad;
+        self
+    }
+
+    // 指定したrayonのスレッドプール上でソートする（parallelフィーチャーが必要）
+    #[cfg(feature = "parallel")]
+    pub fn pool(mut self, pool: &'a rayon::ThreadPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    // この設定でxをcomparatorの順序にソートする
+    // 安定ソートでは比較する要素を複数のスレッドから参照するので、TにはSyncも必要
+    pub fn sort_by<T, F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where
+        T: Send + Sync,
+        F: Sync + Fn(&T, &T) -> Ordering,
+    {
+        if !self.stable {
+            return self.sort_unstable_by(x, comparator);
+        }
+        // 各要素の元の位置を比較の第2キーとしてargsortし、得られた並び順にxを並べ替える
+        self.check_len(x.len())?;
+        let mut indices: Vec<usize> = (0..x.len()).collect();
+        // comparatorで等しい時は、元の位置が前の要素を先にする
+        self.sort_unstable_by(&mut indices, &|&i, &j| {
+            comparator(&x[i], &x[j]).then(i.cmp(&j))
+        })?;
+        permute_in_place(x, &mut indices);
+        Ok(())
+    }
+
+    // stableの設定を無視して、不安定なソートを行う。TにSyncを求めない
+    fn sort_unstable_by<T, F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where
+        T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+    {
+        #[cfg(feature = "parallel")]
+        if let Some(pool) = self.pool {
+            // install内で呼ばれたrayon::joinはpoolのスレッドで実行される
+            let config = Self { pool: None, ..*self };
+            return pool.install(|| config.sort_unstable_by(x, comparator));
+        }
+        self.check_len(x.len())?;
+        let ctx = Context {
+            parallel_depth: self.parallel_depth,
+            ..Context::new(self.threshold)
+        };
+        do_sort(x, true, comparator, &ctx);
+        Ok(())
+    }
+
+    // この設定でソートできる要素数かを確認する
+    fn check_len(&self, len: usize) -> Result<(), SortError> {
+        if !self.pad && len > 1 && power_of_two_exponent(len).is_none() {
+            return Err(SortError::NotPowerOfTwo { len });
+        }
+        check_depth(len)
+    }
+}
+
+impl Default for BitonicConfig<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// すでにcomparatorの順序で並んでいれば、ソートせずにすぐ戻る
+// 確認はO(n)で済むので、ソート済みに近いデータが多い時にはO(n log^2 n)のソートを省ける
+// ソート済みでなければ確認の分だけ遅くなるので、sort_byとは別の関数にしている
+pub fn sort_by_adaptive<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if is_sorted_by(x, comparator) {
+        return Ok(());
+    }
+    sort_by(x, comparator)
+}
+
+// バイトニック列をマージしてソートする。forwardがtrueならcomparatorの順序に、falseなら逆順に並べる
+// xは前半が昇順、後半が降順（またはその逆）に並んだバイトニック列でなければならない
+// 例えば、それぞれソート済みの2つの列を、後ろの列だけ逆順にしてつなげたものを渡せば
+// 全体をソートし直すことなく1つのソート済みの列にまとめられる
+// バイトニック列でない入力に対する結果は規定しない（utils::is_bitonicで確認できる）
+// 要素数は2のべき乗でなければならない
+pub fn bitonic_merge<T, F>(x: &mut [T], forward: bool, comparator: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    // 空のスライスと1要素のスライスはそのままでマージ済み
+    if x.len() <= 1 {
+        return Ok(());
+    }
+    if power_of_two_exponent(x.len()).is_none() {
+        return Err(SortError::NotPowerOfTwo { len: x.len() });
+    }
+    let ctx = Context::new(PARALLEL_THRESHOLD);
+    if forward {
+        sub_sort::<_, _, true>(x, comparator, &ctx);
+    } else {
+        sub_sort::<_, _, false>(x, comparator, &ctx);
+    }
+    Ok(())
+}
+
+// Ordを実装した型のバイトニック列を、orderで指定した順にマージする
+// bitonic_mergeと同じだが、向きをboolではなくSortOrderで指定する
+pub fn bitonic_merge_ordered<T: Ord + Send>(
+    x: &mut [T],
+    order: SortOrder,
+) -> Result<(), SortError> {
+    bitonic_merge(x, is_forward(order), &|a: &T, b: &T| a.cmp(b))
+}
+
+// ハッシュ値のような固定長のバイト列を、辞書順（ビッグエンディアンの整数としての順）にソートする
+// 比較は<[u8]>::cmpで行う。u8のスライスの比較はmemcmpになるので、1バイトずつ比べるより速い
+// [u8; N]のOrdも同じ比較をするので、sort_orderedと結果は変わらない
+pub fn sort_bytes<const N: usize>(x: &mut [[u8; N]], order: SortOrder) -> Result<(), SortError> {
+    sort_by_order(x, order, &|a: &[u8; N], b: &[u8; N]| a[..].cmp(&b[..]))
+}
+
+// u32のスライスを、上位8ビットの値で256個のバケットに振り分けてから、バケットごとにソートする
+// 各バケットの要素数は全体の約1/256になるので、全体をバイトニックソートするより
+// 比較の段数がずっと少なくて済む。振り分けのためにxと同じ要素数の作業用のベクタを確保する
+pub fn sort_u32_radix_hybrid(x: &mut [u32], order: SortOrder) -> Result<(), SortError> {
+    check_depth(x.len())?;
+    // 降順なら上位8ビットが大きいバケットから並べる
+    let bucket = |v: u32| match order {
+        SortOrder::Ascending => (v >> 24) as usize,
+        SortOrder::Descending => 255 - (v >> 24) as usize,
+    };
+
+    // 各バケットの要素数を数え、バケットの開始位置を求める
+    let mut starts = [0usize; 257];
+    for &v in x.iter() {
+        starts[bucket(v) + 1] += 1;
+    }
+    for i in 1..starts.len() {
+        starts[i] += starts[i - 1];
+    }
+
+    // 作業用のベクタにバケット順に並べてから、xに書き戻す
+    let mut buf = vec![0u32; x.len()];
+    let mut next = starts;
+    for &v in x.iter() {
+        let b = bucket(v);
+        buf[next[b]] = v;
+        next[b] += 1;
+    }
+    x.copy_from_slice(&buf);
+
+    // バケット同士の大小はすでに決まっているので、各バケットの中だけをソートすればよい
+    // バケットの要素数は2のべき乗とは限らないが、仮想的な番兵でパディングしてソートされる
+    for w in starts.windows(2) {
+        sort(&mut x[w[0]..w[1]], order)?;
+    }
+    Ok(())
+}
+
+// 長さchunkの区間ごとにバイトニックソートしてから、それらをバイトニックマージでまとめる
+// 各区間は、隣り合う2つがバイトニック列になるよう交互の向きにソートされる
+// chunkをキャッシュに収まる大きさにすれば、区間のソートはキャッシュ上のデータだけで済む
+// xの要素数とchunkはどちらも2のべき乗でなければならない。そうでなければ、
+// 2のべき乗でない方の値をNotPowerOfTwoのlenとして返す
+pub fn sort_chunked<T: Ord + Send>(
+    x: &mut [T],
+    chunk: usize,
+    order: SortOrder,
+) -> Result<(), SortError> {
+    if x.len() <= 1 {
+        return Ok(());
+    }
+    if power_of_two_exponent(x.len()).is_none() {
+        return Err(SortError::NotPowerOfTwo { len: x.len() });
+    }
+    if power_of_two_exponent(chunk).is_none() {
+        return Err(SortError::NotPowerOfTwo { len: chunk });
+    }
+    check_depth(x.len())?;
+    let ctx = Context::new(PARALLEL_THRESHOLD);
+    sort_chunks(x, chunk, is_forward(order), &|a: &T, b: &T| a.cmp(b), &ctx);
+    Ok(())
+}
+
+// do_sortと同じく前半を逆向き、後半をforwardの向きにソートしてからマージするが、
+// 長さchunk以下になった区間は、そこでdo_sortを使ってまとめてソートする
+fn sort_chunks<T, F>(x: &mut [T], chunk: usize, forward: bool, comparator: &F, ctx: &Context)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if x.len() <= chunk {
+        do_sort(x, forward, comparator, ctx);
+        return;
+    }
+    let mid_point = x.len() / 2;
+    let (first, second) = x.split_at_mut(mid_point);
+    if let Some(ctx) = ctx.parallel(mid_point) {
+        join(|| sort_chunks(first, chunk, !forward, comparator, &ctx),
+             || sort_chunks(second, chunk, forward, comparator, &ctx));
+    } else {
+        sort_chunks(first, chunk, !forward, comparator, ctx);
+        sort_chunks(second, chunk, forward, comparator, ctx);
+    }
+    if forward {
+        sub_sort::<_, _, true>(x, comparator, ctx);
+    } else {
+        sub_sort::<_, _, false>(x, comparator, ctx);
+    }
+}
+
+// 要素数nのスライスをソートする時に行われる比較と交換の列を、データを動かさずに返す
+// 各要素は(i, j, forward)の組で、forwardがtrueならi番目に小さい方、j番目に大きい方を置く
+// （falseなら逆に置く）ことを表す。並びはバイトニックソートの比較ネットワークを
+// 順番に実行した時の順序と同じ。do_sortはSMALL_SORT_LEN以下の区間を挿入ソートで並べるので、
+// 実際のソートの比較とは異なる（比較の回数を数えるsort_by_countedの比較とは一致する）
+// 要素数が2のべき乗でない場合は、番兵が相手になる比較を含まない
+pub fn generate_network(n: usize) -> Result<Vec<(usize, usize, bool)>, SortError> {
+    check_depth(n)?;
+    let mut network = Vec::new();
+    network_sort(0, n, true, &mut network);
+    Ok(network)
+}
+
+// do_sortと同じ順序で、offsetから始まる長さlenの区間の比較を追加する
+fn network_sort(
+    offset: usize,
+    len: usize,
+    forward: bool,
+    network: &mut Vec<(usize, usize, bool)>,
+) {
+    if len > 1 {
+        let mid_point = padded_mid_point(len);
+        network_sort(offset, mid_point, !forward, network);
+        network_sort(offset + mid_point, len - mid_point, forward, network);
+        network_merge(offset, len, forward, network);
+    }
+}
+
+// sub_sortとcompare_and_swapと同じ順序で、offsetから始まる長さlenの区間の比較を追加する
+fn network_merge(
+    offset: usize,
+    len: usize,
+    forward: bool,
+    network: &mut Vec<(usize, usize, bool)>,
+) {
+    if len > 1 {
+        let mid_point = padded_mid_point(len);
+        for i in 0..len - mid_point {
+            network.push((offset + i, offset + mid_point + i, forward));
+        }
+        network_merge(offset, mid_point, forward, network);
+        network_merge(offset + mid_point, len - mid_point, forward, network);
+    }
+}
+
+// Box<T>やArc<T>のようなスマートポインタの列を、指す先の値のOrdでorderの順にソートする
+// 比較のクロージャで**a、**bと参照を外す手間を省ける
+// Rc<T>はSendではないので使えない。その場合はsort_by_mutでソートする
+pub fn sort_by_deref<P, T>(x: &mut [P], order: SortOrder) -> Result<(), SortError>
+where
+    P: Deref<Target = T> + Send,
+    T: Ord + ?Sized,
+{
+    sort_by_order(x, order, &|a: &P, b: &P| (**a).cmp(&**b))
+}
+
+// 参照の列を、指す先の値のOrdでorderの順にソートする。sort_by_derefを&Tに限ったもの
+// 参照を複数のスレッドに渡すので、TはSyncでなければならない
+pub fn sort_refs<T: Ord + Sync + ?Sized>(x: &mut [&T], order: SortOrder) -> Result<(), SortError> {
+    sort_by_deref(x, order)
+}
+
+// Comparatorトレイトを実装した比較関数でソートする
+// クロージャの代わりに、名前のついた比較用の構造体を渡せる
+pub fn sort_with<T, C>(x: &mut [T], comparator: &C) -> Result<(), SortError>
+where
+    T: Send,
+    C: Comparator<T> + Sync,
+{
+    sort_by(x, &|a, b| comparator.compare(a, b))
+}
+
+// 要素から取り出したキーを比較してソートする
+// 標準ライブラリのsort_by_keyと同様に、キーは比較のたびにfで計算される
+pub fn sort_by_key<T, K, F>(x: &mut [T], f: &F) -> Result<(), SortError>
+where
+    T: Send,
+    K: Ord,
+    F: Sync + Fn(&T) -> K,
+{
+    sort_by(x, &|a, b| f(a).cmp(&f(b)))
+}
+
+// priorityが返す優先度の小さい順にソートする。キーをu32にしたsort_by_keyと同じ
+// derive(Ord)による宣言順ではなく、別の順序でC言語風の列挙型を並べたい時などに使う
+// sort_byと同じく、要素数がPARALLEL_THRESHOLD以上なら並列に処理する
+pub fn sort_by_priority<T, F>(x: &mut [T], priority: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T) -> u32,
+{
+    sort_by_key(x, priority)
+}
+
+// sort_by_keyと同じだが、キーは各要素について1回だけ計算する
+// キーの列をargsortしてから、その並び順にxを並べ替える。キーの計算が重い時に使う
+// キーの列とインデックスの列のためのメモリを、要素数分確保する
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], f: &F) -> Result<(), SortError>
+where
+    K: Ord + Sync,
+    F: Fn(&T) -> K,
+{
+    // キーは順番に計算するので、fはSyncでなくてもよい
+    let keys: Vec<K> = x.iter().map(f).collect();
+    let mut perm = argsort(&keys, &|a, b| a.cmp(b))?;
+    permute_in_place(x, &mut perm);
+    Ok(())
+}
+
+// キーを取り出す関数とその並び順の組を複数受け取り、先頭の組から順に比較してソートする
+// 例えば「last_nameの昇順、それが等しければfirst_nameの降順」のような並べ方を、
+// then_withをつなげたクロージャを書かずに指定できる
+// すべての組でキーが等しい要素同士は、等しいものとして扱われる
+// 配列に並べるため、キーを取り出す関数はすべて同じ型（例：&dyn Fn(&T) -> K）でなければならない
+pub fn sort_by_keys<T, K, F>(x: &mut [T], keys: &[(F, SortOrder)]) -> Result<(), SortError>
+where
+    T: Send,
+    K: Ord,
+    F: Sync + Fn(&T) -> K,
+{
+    sort_by(x, &|a, b| {
+        for (f, order) in keys {
+            let ordering = match *order {
+                SortOrder::Ascending => f(a).cmp(&f(b)),
+                SortOrder::Descending => f(b).cmp(&f(a)),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    })
+}
+
+// xをソートした時の並び順をインデックスの列として返す。x自体は変更しない
+// 要素を動かす代わりにインデックスの配列に対してバイトニックソートを行う
+pub fn argsort<T, F>(x: &[T], comparator: &F) -> Result<Vec<usize>, SortError>
+where
+    T: Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    argsort_as(x, comparator)
+}
+
+// x[i]がorderの順でソートした時に何番目（0から数える）に来るかを、ranks[i]として返す
+// xは変更しない。等しい要素は、元の位置が前の要素を先の順位にする
+// argsortで得た並び順（順位→元の位置）を逆にたどって、元の位置→順位の対応を作る
+pub fn ranks<T: Ord + Sync>(x: &[T], order: SortOrder) -> Result<Vec<usize>, SortError> {
+    let perm = argsort_then_index(x, order)?;
+    let mut ranks = vec![0; x.len()];
+    for (rank, &i) in perm.iter().enumerate() {
+        ranks[i] = rank;
+    }
+    Ok(ranks)
+}
+
+// xの値ごとに、その値を持つ要素の元の位置をまとめ、(値, 位置の列)をorderの順に並べて返す
+// 位置の列は小さい順に並ぶ。xは変更しない
+// 例：[a, b, a, c, b]なら[(a, [0, 2]), (b, [1, 4]), (c, [3])]
+pub fn sort_grouped<T>(x: &[T], order: SortOrder) -> Result<Vec<(T, Vec<usize>)>, SortError>
+where
+    T: Ord + Clone + Sync,
+{
+    let perm = argsort_then_index(x, order)?;
+    let mut groups: Vec<(T, Vec<usize>)> = Vec::new();
+    for i in perm {
+        // 並び順では等しい要素が隣り合うので、直前のグループと比べるだけでよい
+        match groups.last_mut() {
+            Some((value, indices)) if *value == x[i] => indices.push(i),
+            _ => groups.push((x[i].clone(), vec![i])),
+        }
+    }
+    Ok(groups)
+}
+
+// xをorderの順に並べた時の並び順をargsortで求める。等しい要素は元の位置の小さい順に並べる
+fn argsort_then_index<T: Ord + Sync>(x: &[T], order: SortOrder) -> Result<Vec<usize>, SortError> {
+    // 要素と位置の組をargsortし、要素が等しい時は位置で比べる
+    let keyed: Vec<(&T, usize)> = x.iter().zip(0..).collect();
+    argsort(&keyed, &|a: &(&T, usize), b: &(&T, usize)| {
+        let ordering = match order {
+            SortOrder::Ascending => a.0.cmp(b.0),
+            SortOrder::Descending => b.0.cmp(a.0),
+        };
+        ordering.then(a.1.cmp(&b.1))
+    })
+}
+
+// argsortと同じだが、インデックスを型Iで返す。例：argsort_as::<u32, _, _>(&x, &cmp)
+// xの要素数がIで表せる範囲を超える場合はTooLargeを返す
+pub fn argsort_as<I, T, F>(x: &[T], comparator: &F) -> Result<Vec<I>, SortError>
+where
+    I: IndexInt,
+    T: Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    // 最大のインデックスlen - 1ではなくlenで確認し、0..lenを変換できることを保証する
+    if I::from_usize(x.len()).is_none() {
+        return Err(SortError::TooLarge { len: x.len() });
+    }
+    let mut indices: Vec<I> = (0..x.len()).filter_map(I::from_usize).collect();
+    // インデックスが指すxの要素同士をcomparatorで比較する
+    sort_by(&mut indices, &|i: &I, j: &I| {
+        comparator(&x[i.to_usize()], &x[j.to_usize()])
+    })?;
+    Ok(indices)
+}
+
+// 安定ソート。comparatorで等しいとされた要素は、ソート前の順序を保つ
+// xとは別に、要素数分のusize（64ビット環境なら8nバイト）のメモリを確保する
+pub fn sort_by_stable<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    T: Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    BitonicConfig::new().stable(true).sort_by(x, comparator)
+}
+
+// keysをorderで指定した順にソートし、valuesもkeysと同じ入れ替えで並べ替える
+// keys[i]とvalues[i]は組として一緒に動く。要素数が異なる場合はLengthMismatchを返す
+// argsortと同じくkeysのインデックスの列をソートしてから、その並び順に両方を並べ替える
+pub fn sort_pairs<K, V>(
+    keys: &mut [K],
+    values: &mut [V],
+    order: SortOrder,
+) -> Result<(), SortError>
+where
+    K: Ord + Send + Sync,
+{
+    if keys.len() != values.len() {
+        return Err(SortError::LengthMismatch {
+            keys: keys.len(),
+            values: values.len(),
+        });
+    }
+    let mut indices: Vec<usize> = (0..keys.len()).collect();
+    sort_by_order(&mut indices, order, &|&i, &j| keys[i].cmp(&keys[j]))?;
+    // ソートされるのはインデックスの列だけで、keysとvaluesには同じ交換を1回ずつ行う
+    permute_with(&mut indices, |i, j| {
+        keys.swap(i, j);
+        values.swap(i, j);
+    });
+    Ok(())
+}
+
+// x[i]がx[perm[i]]になるよう、要素を交換しながらその場で並べ替える
+// permはargsortで作ったものなので、utils::apply_permutation_in_placeと違って検証しない
+fn permute_in_place<T>(x: &mut [T], perm: &mut [usize]) {
+    permute_with(perm, |i, j| x.swap(i, j));
+}
+
+// xを先頭から長さstrideずつの組に分け、各組のoffset番目の要素をcomparatorで比較して
+// 組ごと並べ替える。例えばRGBAの画素が並んだバッファを、stride = 4、offset = 1とすれば
+// 画素をGの値でソートできる
+// strideは0より大きく、offsetはstride未満で、xの要素数はstrideで割り切れなければならない
+// そうでなければInvalidStrideを返す。組の数は2のべき乗でなくてもよい
+pub fn sort_strided_by<T, F>(
+    x: &mut [T],
+    stride: usize,
+    offset: usize,
+    comparator: &F,
+) -> Result<(), SortError>
+where
+    T: Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if stride == 0 || offset >= stride || x.len() % stride != 0 {
+        return Err(SortError::InvalidStride {
+            len: x.len(),
+            stride,
+            offset,
+        });
+    }
+    // 組のインデックスの列をソートしてから、その並び順に組ごと入れ替える
+    let mut indices: Vec<usize> = (0..x.len() / stride).collect();
+    sort_by(&mut indices, &|&i, &j| {
+        comparator(&x[i * stride + offset], &x[j * stride + offset])
+    })?;
+    permute_with(&mut indices, |i, j| {
+        let (a, b) = if i < j { (i, j) } else { (j, i) };
+        let (head, tail) = x.split_at_mut(b * stride);
+        head[a * stride..(a + 1) * stride].swap_with_slice(&mut tail[..stride]);
+    });
+    Ok(())
+}
+
+// 呼び出し側が用意したrayonのスレッドプール上でソートする（parallelフィーチャーが必要）
+// グローバルなスレッドプールを使う他の並列処理と干渉させたくない時に使う
+// ThreadPoolBuilder::spawn_handlerでスレッドの作り方を変えたプールも渡せるので、
+// 各スレッドを特定のCPUに固定（アフィニティを設定）すれば、ソートをそのCPUだけで実行できる
+// （設定は呼び出し側で行う。テストのsort_u32_in_custom_spawned_poolを参照）
+#[cfg(feature = "parallel")]
+pub fn sort_by_in_pool<T, F>(
+    pool: &rayon::ThreadPool,
+    x: &mut [T],
+    comparator: &F,
+) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    BitonicConfig::new().pool(pool).sort_unstable_by(x, comparator)
+}
+
+// tune_thresholdが試すしきい値の候補
+#[cfg(feature = "std")]
+const TUNE_CANDIDATES: [usize; 4] = [256, 1024, 4096, 16384];
+
+// sampleの複製をしきい値の候補ごとに数回ソートし、最も速かったしきい値を返す（stdフィーチャーが必要）
+// 実行時間を測るだけの簡単な方法なので、他の処理の影響などで結果は変わりうる
+// あくまで目安として、sort_with_thresholdなどに渡すしきい値を選ぶのに使う
+#[cfg(feature = "std")]
+pub fn tune_threshold<T: Ord + Clone + Send>(sample: &[T]) -> usize {
+    use std::time::{Duration, Instant};
+
+    // 各候補を試す回数。最も短かった時間をその候補の結果とする
+    const REPETITIONS: usize = 3;
+
+    let mut best = (Duration::MAX, PARALLEL_THRESHOLD);
+    for &threshold in &TUNE_CANDIDATES {
+        for _ in 0..REPETITIONS {
+            let mut x = sample.to_vec();
+            let start = Instant::now();
+            do_sort(&mut x, true, &|a: &T, b: &T| a.cmp(b), &Context::new(threshold));
+            let elapsed = start.elapsed();
+            if elapsed < best.0 {
+                best = (elapsed, threshold);
+            }
+        }
+    }
+    best.1
+}
+
+// sort_byでソートし、comparatorがパニックしたらComparatorPanickedを返す（stdフィーチャーが必要）
+// rayonのワーカースレッドで起きたパニックも、joinを通じてここで捕まえられる
+// パニックした時点までに交換された要素はそのままなので、xは途中までソートされた状態で残る
+#[cfg(feature = "std")]
+pub fn sort_by_catch_unwind<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering + std::panic::RefUnwindSafe,
+{
+    // xはパニック後に途中までソートされた状態で見えることを、呼び出し側に許してもらう
+    let x = std::panic::AssertUnwindSafe(x);
+    match std::panic::catch_unwind(move || sort_by(x.0, comparator)) {
+        Ok(result) => result,
+        Err(_) => Err(SortError::ComparatorPanicked),
+    }
+}
+
+// sort_byでソートし、かかった時間を返す（stdフィーチャーが必要）
+// criterionを使うほどではない、例などでの簡単な計測に使う
+#[cfg(feature = "std")]
+pub fn sort_by_timed<T, F>(x: &mut [T], comparator: &F) -> Result<std::time::Duration, SortError>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let start = std::time::Instant::now();
+    sort_by(x, comparator)?;
+    Ok(start.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+    // 親モジュール(first)のsort関数を使用する
+    use super::{
+        BitonicConfig, BitonicSortExt, BitonicSorter, BlockSorter, Context, Counters,
+        PARALLEL_THRESHOLD, argsort, argsort_as, bitonic_merge, bitonic_merge_ordered, check_depth,
+        comparator_count, do_sort, generate_network, median_by, partial_sort_by, ranks,
+        recursion_depth, sort, sort_array, sort_asc, sort_both, sort_by, sort_by_adaptive,
+        sort_by_cached_key, sort_by_cancellable, sort_by_counted, sort_by_deref,
+        sort_by_float_total, sort_by_if, sort_by_key, sort_by_keys, sort_by_mut, sort_by_order,
+        sort_by_partial, sort_by_priority, sort_by_reporting, sort_by_stable, sort_by_trace,
+        sort_by_with_parallel_depth, sort_by_with_progress, sort_by_with_threshold,
+        sort_by_yielding, sort_bytes, sort_chunked, sort_dedup, sort_desc, sort_dir, sort_grouped,
+        sort_into_vec, sort_iterative, sort_ordered, sort_pairs, sort_prefix_by, sort_range_by,
+        sort_refs, sort_strided_by, sort_u32_radix_hybrid, sort_valid_by, sort_vec, sort_with,
+        sort_with_threshold, sorted, sorted_by,
+    };
+    #[cfg(feature = "parallel")]
+    use super::sort_by_in_pool;
+    #[cfg(feature = "std")]
+    use super::{TUNE_CANDIDATES, sort_by_catch_unwind, sort_by_timed, try_sort_by, tune_threshold};
+    #[cfg(feature = "std")]
+    use crate::TrySortError;
+    use crate::SortOrder::*;
+    use crate::{Comparator, SortError, direction};
+    use core::cmp::Ordering;
+    use core::ops::Range;
+    use crate::utils::{
+        new_u32_vec, new_u32_vec_in_range, new_u32_vec_seeded, new_vec, is_sorted_ascending,
+        is_sorted_by, is_sorted_descending, reverse_in_place,
+    };
+
+    // #[test]のついた関数はcargo testとした時に実行される
+    #[test]
+    fn sort_u32_ascending() {
+        // テストデータとしてu32型のベクタを作成しxに束縛する
+        // sort関数によって内容が更新されるので、可変を表すmutが必要
+        let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+
+        // xのスライスを作成し、sort関数を呼び出す
+        // &mut x は&mut x[..]と書いてもいい
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+
+        // xの要素が昇順にソートされていることを確認する
+        assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
+    }
+
+    #[test]
+    fn sort_u32_descending() {
+        let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        // xの要素が降順にソートされていることを確認する
+        assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
+    }
+
+    #[test]
+    fn sort_descending_without_comparator_inversion() {
+        // 降順は比較の引数を入れ替えずに、ソートの向きを反転させて得る
+        let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
+
+        // 2のべき乗でない要素数でも、番兵は末尾に留まる
+        let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21];
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![330, 30, 21, 20, 11, 10, 4]);
+
+        // 昇順にソートしてから逆順にしても同じ結果になる
+        let mut y = new_u32_vec(1000);
+        let mut z = y.clone();
+        assert_eq!(sort(&mut y, Descending), Ok(()));
+        assert_eq!(sort(&mut z, Ascending), Ok(()));
+        reverse_in_place(&mut z);
+        assert_eq!(y, z);
+
+        // sort_orderedも同じ結果になる
+        let mut y = vec![10, 30, 11, 20, 4, 330, 21];
+        assert_eq!(sort_ordered(&mut y, Descending), Ok(()));
+        assert_eq!(y, x);
+    }
+
+    #[test]
+    fn sort_empty_and_single() {
+        let mut x: Vec<u32> = vec![];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![]);
+
+        let mut x = vec![42];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![42]);
+    }
+
+    #[test]
+    fn sort_u32_many_duplicates() {
+        // 0から99までの値しかないので、同じ値の要素が多く含まれる
+        let mut x = new_u32_vec_in_range(10000, 0..100, [0; 16]);
+        assert!(x.iter().all(|&v| v < 100));
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert!(is_sorted_ascending(&x));
+    }
+
+    #[test]
+    fn sort_chunked_large() {
+        let mut x = new_u32_vec(1 << 20);
+        assert_eq!(sort_chunked(&mut x, 1 << 14, Ascending), Ok(()));
+        assert!(is_sorted_ascending(&x));
+
+        let mut x = new_u32_vec(1024);
+        assert_eq!(sort_chunked(&mut x, 16, Descending), Ok(()));
+        assert!(is_sorted_descending(&x));
+
+        // 要素数とchunkのうち、2のべき乗でない方がエラーになる
+        let mut x = new_u32_vec(1000);
+        let result = sort_chunked(&mut x, 16, Ascending);
+        assert_eq!(result, Err(SortError::NotPowerOfTwo { len: 1000 }));
+        let mut x = new_u32_vec(1024);
+        let result = sort_chunked(&mut x, 100, Ascending);
+        assert_eq!(result, Err(SortError::NotPowerOfTwo { len: 100 }));
+    }
+
+    #[test]
+    fn sort_pairs_values_follow_keys() {
+        let mut keys = vec![30, 10, 40, 20, 50];
+        let mut values = vec!["thirty", "ten", "forty", "twenty", "fifty"];
+        assert_eq!(sort_pairs(&mut keys, &mut values, Ascending), Ok(()));
+        assert_eq!(keys, vec![10, 20, 30, 40, 50]);
+        assert_eq!(values, vec!["ten", "twenty", "thirty", "forty", "fifty"]);
+
+        assert_eq!(sort_pairs(&mut keys, &mut values, Descending), Ok(()));
+        assert_eq!(keys, vec![50, 40, 30, 20, 10]);
+        assert_eq!(values, vec!["fifty", "forty", "thirty", "twenty", "ten"]);
+
+        let mut values = vec!["one"];
+        assert_eq!(
+            sort_pairs(&mut keys, &mut values, Ascending),
+            Err(SortError::LengthMismatch { keys: 5, values: 1 })
+        );
+
+        // valuesは順番に並べ替えるだけなので、Sendでない型（Rc）でもよい
+        let mut keys = vec![3, 1, 2];
+        let mut values: Vec<Rc<&str>> = vec![Rc::new("c"), Rc::new("a"), Rc::new("b")];
+        assert_eq!(sort_pairs(&mut keys, &mut values, Ascending), Ok(()));
+        let values: Vec<&str> = values.iter().map(|v| **v).collect();
+        assert_eq!(values, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn generate_network_matches_sort() {
+        // n = 8なら (8 / 4) * 3 * 4 = 24回比較する
+        let network = generate_network(8).unwrap();
+        assert_eq!(network.len(), 24);
+        // 最初は先頭の2要素を昇順に並べる比較になる
+        assert_eq!(network[0], (0, 1, true));
+
+        // 比較の列をそのまま適用すると、sortと同じ結果になる
+        for &n in &[8, 13] {
+            let mut x = new_u32_vec(n);
+            for (i, j, forward) in generate_network(n).unwrap() {
+                let swap = if forward { x[i] > x[j] } else { x[i] < x[j] };
+                if swap {
+                    x.swap(i, j);
+                }
+            }
+            assert!(is_sorted_ascending(&x));
+        }
+    }
+
+    #[test]
+    fn sort_by_mut_counts_calls() {
+        // 呼ばれた回数を、クロージャが捕捉した変数に数える
+        let mut calls = 0;
+        let mut x = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        let result = sort_by_mut(&mut x, &mut |a: &u32, b: &u32| {
+            calls += 1;
+            a.cmp(b)
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
+        // 8要素なら24回比較する
+        assert_eq!(calls, 24);
+    }
+
+    #[test]
+    fn sort_smart_pointers_by_deref() {
+        let mut x: Vec<Box<u32>> = vec![10, 30, 11, 20, 4, 330, 21, 110]
+            .into_iter()
+            .map(Box::new)
+            .collect();
+        assert_eq!(sort_by_deref(&mut x, Ascending), Ok(()));
+        let values: Vec<u32> = x.iter().map(|b| **b).collect();
+        assert_eq!(values, vec![4, 10, 11, 20, 21, 30, 110, 330]);
+
+        // 大きさの決まらない型を指すArc<str>もソートできる
+        let mut x: Vec<std::sync::Arc<str>> = vec!["Rust".into(), "is".into(), "fast".into()];
+        assert_eq!(sort_by_deref(&mut x, Descending), Ok(()));
+        let words: Vec<&str> = x.iter().map(|s| &**s).collect();
+        assert_eq!(words, vec!["is", "fast", "Rust"]);
+    }
+
+    #[test]
+    fn sort_strided_every_4th() {
+        // RGBAの画素が5つ並んだバッファを、Gの値（各組の1番目）でソートする
+        let mut x: Vec<u8> = vec![
+            1, 50, 2, 255, //
+            3, 10, 4, 255, //
+            5, 40, 6, 255, //
+            7, 30, 8, 255, //
+            9, 20, 10, 255,
+        ];
+        assert_eq!(sort_strided_by(&mut x, 4, 1, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(
+            x,
+            vec![
+                3, 10, 4, 255, //
+                9, 20, 10, 255, //
+                7, 30, 8, 255, //
+                5, 40, 6, 255, //
+                1, 50, 2, 255,
+            ]
+        );
+
+        assert_eq!(
+            sort_strided_by(&mut x, 3, 1, &|a, b| a.cmp(b)),
+            Err(SortError::InvalidStride { len: 20, stride: 3, offset: 1 })
+        );
+        assert!(sort_strided_by(&mut x, 4, 4, &|a, b| a.cmp(b)).is_err());
+    }
+
+    #[test]
+    fn sort_str_ascending() {
+        // 文字列のベクタを作りソートする
+        let mut x = vec![
+            "Rust",
+            "is",
+            "fast",
+            "and",
+            "memory-efficient",
+            "with",
+            "no",
+            "GC",
+        ];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(
+            x,
+            vec![
+                "GC",
+                "Rust",
+                "and",
+                "fast",
+                "is",
+                "memory-efficient",
+                "no",
+                "with"
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_str_descending() {
+        let mut x = vec![
+            "Rust",
+            "is",
+            "fast",
+            "and",
+            "memory-efficient",
+            "with",
+            "no",
+            "GC",
+        ];
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(
+            x,
+            vec![
+                "with",
+                "no",
+                "memory-efficient",
+                "is",
+                "fast",
+                "and",
+                "Rust",
+                "GC"
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_char_descending() {
+        let mut x: Vec<char> = "bitonic".chars().collect();
+        assert_eq!(sort_ordered(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec!['t', 'o', 'n', 'i', 'i', 'c', 'b']);
+    }
+
+    #[test]
+    fn sort_bytes_lexicographic() {
+        let mut x = vec![
+            [0x12, 0x34, 0x56, 0x78],
+            [0x00, 0xff, 0xff, 0xff],
+            [0x12, 0x34, 0x00, 0x00],
+            [0xff, 0x00, 0x00, 0x00],
+            [0x00, 0xff, 0xff, 0xfe],
+        ];
+        assert_eq!(sort_bytes(&mut x, Ascending), Ok(()));
+        assert_eq!(
+            x,
+            vec![
+                [0x00, 0xff, 0xff, 0xfe],
+                [0x00, 0xff, 0xff, 0xff],
+                [0x12, 0x34, 0x00, 0x00],
+                [0x12, 0x34, 0x56, 0x78],
+                [0xff, 0x00, 0x00, 0x00],
+            ]
+        );
+
+        // 辞書順はビッグエンディアンで表した整数の順と一致する
+        let mut x: Vec<[u8; 4]> = new_u32_vec(1000).into_iter().map(u32::to_be_bytes).collect();
+        assert_eq!(sort_bytes(&mut x, Descending), Ok(()));
+        let values: Vec<u32> = x.into_iter().map(u32::from_be_bytes).collect();
+        assert!(is_sorted_descending(&values));
+    }
+
+    #[test]
+    fn sort_dir_both_directions() {
+        // マーカー型はSortOrderのバリアントと同じ名前なので、モジュール名をつけて指定する
+        let mut x = new_u32_vec(1000);
+        assert_eq!(sort_dir::<direction::Ascending, _>(&mut x), Ok(()));
+        assert!(is_sorted_ascending(&x));
+        assert_eq!(sort_dir::<direction::Descending, _>(&mut x), Ok(()));
+        assert!(is_sorted_descending(&x));
+
+        let mut x: Vec<char> = "bitonic".chars().collect();
+        assert_eq!(sort_asc(&mut x), Ok(()));
+        assert_eq!(x, vec!['b', 'c', 'i', 'i', 'n', 'o', 't']);
+        assert_eq!(sort_desc(&mut x), Ok(()));
+        assert_eq!(x, vec!['t', 'o', 'n', 'i', 'i', 'c', 'b']);
+    }
+
+    #[test]
+    fn sort_u32_not_power_of_two() {
+        let mut x = vec![10, 30, 11]; // x.len()が2のべき乗になっていない
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(x, vec![10, 11, 30]);
+
+        let mut x = vec![10, 30, 11, 20, 4];
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![30, 20, 11, 10, 4]);
+    }
+
+    #[test]
+    fn sort_u32_padded_lengths() {
+        for &len in &[3, 5, 6, 7, 100, 1000] {
+            let mut expected = new_u32_vec(len);
+            let mut x = expected.clone();
+            expected.sort();
+            assert_eq!(sort(&mut x, Ascending), Ok(()));
+            // 番兵が結果に現れていれば標準ライブラリのソート結果と一致しない
+            assert_eq!(x, expected, "len: {}", len);
+
+            let mut x = new_u32_vec(len);
+            expected.reverse();
+            assert_eq!(sort(&mut x, Descending), Ok(()));
+            assert_eq!(x, expected, "len: {}", len);
+        }
+    }
+
+    // 構造体Studentを定義する
+    // 構造体は関連する値を一つにまとめたデータ構造。複数のデータフィールドを持つ
+    // deriveアトリビュートを使い、DebugトレイトとPartialEqトレイトを自動導出する
+    #[derive(Debug, PartialEq)]
+    struct Student {
+        first_name: String, // first_bame(名前)フィールド。String型
+        last_name: String,  // last_name(名字)フィールド。String型
+        age: u8,            // age(年齢)フィールド。u8型(8ビット符号なし整数)
+    }
+
+    // implブロックを使うと対象の型に関連関数やメソッドを実装できる
+    impl Student {
+        // 構造体Studentを初期化して返す。Selfはimpl対象の型（Student）の別名
+        fn new(first_name: &str, last_name: &str, age: u8) -> Self {
+            Self {
+                // to_stringメソッドで&str型の引数からString型の値を作る。詳しくは5章で説明
+                first_name: first_name.to_string(),
+                last_name: last_name.to_string(),
+                age, // フィールドと変数が同じ名前なら省略形で書ける（JSみたいに
+            }
+        }
+    }
+
+    //    impl PartialEq for Student {
+    //        fn eq(&self, other: &self) -> bool {
+    //             selfとotherですべてのフィールド同士を比較して、
+    //             どのフィールドも等しいならselfとotherは等しい
     //            self.first_name == other.first_name
     //            && self.last_name == other.last_name
     //            && self.age == other.age
@@ -225,69 +2251,1352 @@ mod tests {
     //    }
 
     #[test]
-    fn sort_students_by_age_ascending() {
-        // 4人分のテストデータを作成
-        let taro = Student::new("Taro", "Yamada", 16);
-        let hanako = Student::new("Hanako", "Yamada", 14);
-        let kyoko = Student::new("Kyoko", "Ito", 15);
-        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+    fn sort_students_by_age_ascending() {
+        // 4人分のテストデータを作成
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+
+        // ソート対象のベクタを作成する
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+
+        // ソート後の期待値を作成する
+        let expected = vec![&hanako, &kyoko, &taro, &ryosuke];
+
+        assert_eq!(
+            // sort_by関数でソートする。第荷引数はソート順を決めるクロージャ
+            // 引数に2つのStudent構造体を取り、ageフィールドの値をcmpメソッドで
+            // 比較することで大小を決定する
+            sort_by(&mut x, &|a, b| a.age.cmp(&b.age)),
+            Ok(())
+        );
+
+        assert_eq!(x, expected);
+        // sort_byに渡したものと同じクロージャでソート済みかを確認できる
+        assert!(is_sorted_by(&x, &|a, b| a.age.cmp(&b.age)));
+        assert!(!is_sorted_by(&x, &|a, b| b.age.cmp(&a.age)));
+    }
+
+    #[test]
+    fn sort_by_cached_key_calls_once() {
+        let calls = Cell::new(0);
+        let key = |s: &String| {
+            calls.set(calls.get() + 1);
+            s.len()
+        };
+        let mut x: Vec<String> = new_u32_vec(1000).iter().map(|v| v.to_string()).collect();
+        assert_eq!(sort_by_cached_key(&mut x, &key), Ok(()));
+        assert_eq!(calls.get(), 1000);
+        assert!(is_sorted_by(&x, &|a, b| a.len().cmp(&b.len())));
+    }
+
+    #[test]
+    fn block_sorter_merges_blocks() {
+        let mut sorter = BlockSorter::new(|a: &u32, b: &u32| a.cmp(b));
+        let blocks = [new_u32_vec(1024), new_u32_vec_seeded(256, [1; 16]), vec![5, 1, 4, 2]];
+        for block in &blocks {
+            assert_eq!(sorter.push_block(block.clone()), Ok(()));
+        }
+        let merged = sorter.finish().unwrap();
+
+        let mut expected: Vec<u32> = blocks.concat();
+        expected.sort();
+        assert_eq!(merged, expected);
+
+        // 要素数がばらばらのブロックでもまとめられる
+        let mut sorter = BlockSorter::new(|a: &u32, b: &u32| b.cmp(a));
+        let mut expected = Vec::new();
+        for (i, &len) in [3, 100, 1, 33, 7, 0, 64].iter().enumerate() {
+            let block = new_u32_vec_in_range(len, 0..50, [i as u8; 16]);
+            expected.extend_from_slice(&block);
+            assert_eq!(sorter.push_block(block), Ok(()));
+        }
+        expected.sort_by(|a, b| b.cmp(a));
+        assert_eq!(sorter.finish(), Ok(expected));
+
+        // ブロックを1つも受け取らなければ空のベクタを返す
+        let sorter = BlockSorter::new(|a: &u32, b: &u32| a.cmp(b));
+        assert_eq!(sorter.finish(), Ok(vec![]));
+    }
+
+    #[test]
+    fn sort_refs_descending() {
+        let values = [3u32, 10, 1, 7, 7, 2];
+        let mut x: Vec<&u32> = values.iter().collect();
+        assert_eq!(sort_refs(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![&10, &7, &7, &3, &2, &1]);
+
+        // 参照先がスライスのような大きさの決まらない型でもよい
+        let mut words: Vec<&str> = vec!["pear", "apple", "fig"];
+        assert_eq!(sort_refs(&mut words, Ascending), Ok(()));
+        assert_eq!(words, vec!["apple", "fig", "pear"]);
+    }
+
+    // 年齢で比較するComparator
+    struct ByAge;
+
+    impl Comparator<&Student> for ByAge {
+        fn compare(&self, a: &&Student, b: &&Student) -> Ordering {
+            a.age.cmp(&b.age)
+        }
+    }
+
+    #[test]
+    fn sort_students_with_comparator() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        assert_eq!(sort_with(&mut x, &ByAge), Ok(()));
+        assert_eq!(x, vec![&hanako, &kyoko, &taro, &ryosuke]);
+
+        // クロージャもComparatorとして渡せる
+        assert_eq!(sort_with(&mut x, &|a: &&Student, b: &&Student| b.age.cmp(&a.age)), Ok(()));
+        assert_eq!(x, vec![&ryosuke, &taro, &kyoko, &hanako]);
+    }
+
+    #[test]
+    fn sort_student_by_name_ascending() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 16);
+        let kyoko = Student::new("Kyoko", "Ito", 16);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 16);
+
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        let expected = vec![&ryosuke, &kyoko, &hanako, &taro];
+
+        assert_eq!(
+            sort_by(
+                &mut x,
+                // まずlast_nameを比較する
+                &|a, b| a
+                    .last_name
+                    .cmp(&b.last_name)
+                    // もしlast_nameが等しくない（LessまたはGreater）ならそれを返す
+                    // last_nameが等しい（Equal）ならfirst_nameを比較する
+                    .then_with(|| a.first_name.cmp(&b.first_name))
+            ),
+            Ok(())
+        );
+        assert_eq!(x, expected);
+    }
+
+    #[test]
+    fn sort_students_by_keys() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 16);
+        let kyoko = Student::new("Kyoko", "Ito", 16);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 16);
+        let last_name = |s: &&Student| s.last_name.clone();
+        let first_name = |s: &&Student| s.first_name.clone();
+
+        // sort_student_by_name_ascendingと同じく、last_name、first_nameの順に比較する
+        // 2つのクロージャは型が異なるので、トレイトオブジェクトの参照として並べる
+        type NameKey<'a> = &'a (dyn Fn(&&Student) -> String + Sync);
+        let keys: [(NameKey, _); 2] = [(&last_name, Ascending), (&first_name, Ascending)];
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        assert_eq!(sort_by_keys(&mut x, &keys), Ok(()));
+        assert_eq!(x, vec![&ryosuke, &kyoko, &hanako, &taro]);
+
+        // first_nameだけ降順にすると、同じYamadaの2人の順序が入れ替わる
+        let keys: [(NameKey, _); 2] = [(&last_name, Ascending), (&first_name, Descending)];
+        assert_eq!(sort_by_keys(&mut x, &keys), Ok(()));
+        assert_eq!(x, vec![&ryosuke, &kyoko, &taro, &hanako]);
+    }
+
+    #[test]
+    fn sort_students_by_key() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        let expected = vec![&hanako, &kyoko, &taro, &ryosuke];
+
+        // クロージャは比較する2要素ではなく、1要素からキー（age）を取り出す
+        assert_eq!(sort_by_key(&mut x, &|s| s.age), Ok(()));
+        assert_eq!(x, expected);
+    }
+
+    #[test]
+    fn sort_enum_by_priority() {
+        // 宣言順（derive(Ord)の順序）とは違う優先度で並べる
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        enum Level {
+            Info,
+            Warning,
+            Error,
+            Critical,
+        }
+        let priority = |level: &Level| match level {
+            Level::Critical => 0,
+            Level::Error => 1,
+            Level::Info => 2,
+            Level::Warning => 3,
+        };
+
+        let mut x = vec![Level::Info, Level::Critical, Level::Warning, Level::Error];
+        assert_eq!(sort_by_priority(&mut x, &priority), Ok(()));
+        assert_eq!(x, vec![Level::Critical, Level::Error, Level::Info, Level::Warning]);
+
+        // しきい値を超える要素数なら並列に処理される
+        let levels = [Level::Info, Level::Warning, Level::Error, Level::Critical];
+        let mut x: Vec<Level> = new_u32_vec(PARALLEL_THRESHOLD * 4)
+            .into_iter()
+            .map(|v| levels[(v % 4) as usize])
+            .collect();
+        assert_eq!(sort_by_priority(&mut x, &priority), Ok(()));
+        let priorities: Vec<u32> = x.iter().map(priority).collect();
+        assert!(is_sorted_ascending(&priorities));
+    }
+
+    #[test]
+    fn sort_students_by_order() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+        let by_age = |a: &&Student, b: &&Student| a.age.cmp(&b.age);
+
+        let mut ascending = vec![&taro, &hanako, &kyoko, &ryosuke];
+        assert_eq!(sort_by_order(&mut ascending, Ascending, &by_age), Ok(()));
+        assert_eq!(ascending, vec![&hanako, &kyoko, &taro, &ryosuke]);
+
+        // 同じcomparatorで降順にすると、昇順の結果を逆にしたものになる
+        let mut descending = vec![&taro, &hanako, &kyoko, &ryosuke];
+        assert_eq!(sort_by_order(&mut descending, Descending, &by_age), Ok(()));
+        ascending.reverse();
+        assert_eq!(descending, ascending);
+    }
+
+    #[test]
+    fn sort_u32_large() {
+        {
+            // 乱数で65,536要素のデータ列を作る（65,536は2の16乗）
+            let mut x = new_u32_vec(65536);
+            // 昇順にソートする
+            assert_eq!(sort(&mut x, Ascending), Ok(()));
+            assert!(is_sorted_ascending(&x));
+        }
+        {
+            let mut x = new_u32_vec(65536);
+            assert_eq!(sort(&mut x, Descending), Ok(()));
+            assert!(is_sorted_descending(&x));
+        }
+    }
+
+    #[test]
+    fn sort_i32_random() {
+        let mut x: Vec<i32> = new_vec(1024);
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert!(is_sorted_ascending(&x));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert!(is_sorted_descending(&x));
+    }
+
+    #[test]
+    fn sort_u32_many_seeds() {
+        // シードを変えながら100通りのデータをソートする
+        for i in 0..100u8 {
+            let mut x = new_u32_vec_seeded(1000, [i; 16]);
+            assert_eq!(sort(&mut x, Ascending), Ok(()));
+            assert!(is_sorted_ascending(&x), "seed: {}", i);
+        }
+    }
+
+    #[test]
+    fn sort_u32_with_threshold() {
+        // しきい値0（すべて並列）とusize::MAX（すべて順次）で同じ結果になることを確認する
+        for &threshold in &[0, 16, usize::MAX] {
+            let mut x = new_u32_vec(1024);
+            assert_eq!(sort_with_threshold(&mut x, Ascending, threshold), Ok(()));
+            assert!(is_sorted_ascending(&x));
+
+            let mut x = new_u32_vec(1000);
+            assert_eq!(sort_with_threshold(&mut x, Descending, threshold), Ok(()));
+            assert!(is_sorted_descending(&x));
+        }
+    }
+
+    #[test]
+    fn sort_by_with_threshold_deterministic() {
+        // キーが等しい要素を多く含むデータを、キーだけで比較してソートする
+        // 等しい要素の並びまで一致するかを、元の位置（2つ目の値）で確認できる
+        let x: Vec<(u32, usize)> = new_u32_vec(100_000)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (v % 64, i))
+            .collect();
+        let results: Vec<Vec<(u32, usize)>> = [256, 4096, usize::MAX]
+            .iter()
+            .map(|&threshold| {
+                let mut y = x.clone();
+                let result = sort_by_with_threshold(&mut y, &|a, b| a.0.cmp(&b.0), threshold);
+                assert_eq!(result, Ok(()));
+                y
+            })
+            .collect();
+        assert!(is_sorted_by(&results[0], &|a, b| a.0.cmp(&b.0)));
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[0], results[2]);
+    }
+
+    #[test]
+    fn recursion_depth_of_sizes() {
+        assert_eq!(recursion_depth(0), 0);
+        assert_eq!(recursion_depth(1), 0);
+        assert_eq!(recursion_depth(2), 1);
+        assert_eq!(recursion_depth(3), 2);
+        assert_eq!(recursion_depth(1024), 10);
+        assert_eq!(recursion_depth(1025), 11);
+        assert_eq!(recursion_depth(usize::MAX), usize::BITS);
+
+        // 上限を超える要素数は、スライスを作らずに確認だけ行う
+        assert_eq!(check_depth(1 << 20), Ok(()));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(check_depth((1 << 40) + 1), Err(SortError::TooLarge { len: (1 << 40) + 1 }));
+    }
+
+    #[test]
+    fn sort_by_with_parallel_depth_zero() {
+        // 並列に処理しない深さ0の結果は、しきい値usize::MAXで順番にソートした結果と同じになる
+        let x: Vec<(u32, usize)> = new_u32_vec(65536)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (v % 64, i))
+            .collect();
+        let mut sequential = x.clone();
+        let result = sort_by_with_threshold(&mut sequential, &|a, b| a.0.cmp(&b.0), usize::MAX);
+        assert_eq!(result, Ok(()));
+        for &depth in &[0, 2] {
+            let mut y = x.clone();
+            assert_eq!(sort_by_with_parallel_depth(&mut y, &|a, b| a.0.cmp(&b.0), depth), Ok(()));
+            assert_eq!(y, sequential);
+        }
+    }
+
+    #[test]
+    fn sort_by_with_threshold_zero() {
+        let mut x = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(sort_by_with_threshold(&mut x, &|a, b| b.cmp(a), 0), Ok(()));
+        assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn sort_u32_in_pool() {
+        // 2スレッドだけのスレッドプールを作り、その上でソートする
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let mut x = new_u32_vec(65536);
+        assert_eq!(sort_by_in_pool(&pool, &mut x, &|a, b| a.cmp(b)), Ok(()));
+        assert!(is_sorted_ascending(&x));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn sort_u32_in_custom_spawned_pool() {
+        // spawn_handlerで、プールのスレッドを自分で作る
+        // 実際にCPUに固定するなら、スレッドの中でrun()の前にアフィニティを設定する
+        // （例：core_affinityクレートのset_for_current）。ここでは名前をつけるだけにする
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .spawn_handler(|thread| {
+                std::thread::Builder::new()
+                    .name(format!("pinned-sort-{}", thread.index()))
+                    .spawn(move || {
+                        // ここでアフィニティを設定する
+                        thread.run()
+                    })?;
+                Ok(())
+            })
+            .build()
+            .unwrap();
+
+        // comparatorがプールの外のスレッドで呼ばれたら記録する
+        let outside = AtomicBool::new(false);
+        let comparator = |a: &u32, b: &u32| {
+            let name = std::thread::current().name().map(|n| n.starts_with("pinned-sort-"));
+            if name != Some(true) {
+                outside.store(true, AtomicOrdering::Relaxed);
+            }
+            a.cmp(b)
+        };
+        let mut x = new_u32_vec(65536);
+        assert_eq!(sort_by_in_pool(&pool, &mut x, &comparator), Ok(()));
+        assert!(is_sorted_ascending(&x));
+        assert!(!outside.load(AtomicOrdering::Relaxed));
+    }
+
+    #[test]
+    fn argsort_u32() {
+        let x = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        let indices = argsort(&x, &|a, b| a.cmp(b)).unwrap();
+        assert_eq!(indices, vec![4, 0, 2, 3, 6, 1, 7, 5]);
+        // 元のスライスは変更されない
+        assert_eq!(x, vec![10, 30, 11, 20, 4, 330, 21, 110]);
+    }
+
+    #[test]
+    fn ranks_of_elements() {
+        assert_eq!(ranks(&[30, 10, 20], Ascending), Ok(vec![2, 0, 1]));
+        assert_eq!(ranks(&[30, 10, 20], Descending), Ok(vec![0, 2, 1]));
+        // 等しい要素は元の位置の順に順位がつく
+        assert_eq!(ranks(&[5, 1, 5, 1], Ascending), Ok(vec![2, 0, 3, 1]));
+        assert_eq!(ranks(&[5, 1, 5, 1], Descending), Ok(vec![0, 2, 1, 3]));
+
+        // 順位の位置に要素を置くと、ソートした結果になる
+        let x = new_u32_vec(1000);
+        let r = ranks(&x, Ascending).unwrap();
+        let mut placed = vec![0; x.len()];
+        for (i, &rank) in r.iter().enumerate() {
+            placed[rank] = x[i];
+        }
+        assert!(is_sorted_ascending(&placed));
+    }
+
+    #[test]
+    fn sort_grouped_indices() {
+        let x = ['a', 'b', 'a', 'c', 'b'];
+        assert_eq!(
+            sort_grouped(&x, Ascending),
+            Ok(vec![('a', vec![0, 2]), ('b', vec![1, 4]), ('c', vec![3])])
+        );
+        assert_eq!(
+            sort_grouped(&x, Descending),
+            Ok(vec![('c', vec![3]), ('b', vec![1, 4]), ('a', vec![0, 2])])
+        );
+        let empty: [u32; 0] = [];
+        assert_eq!(sort_grouped(&empty, Ascending), Ok(vec![]));
+
+        // すべての位置がちょうど1回ずつ現れる
+        let x = new_u32_vec_in_range(1000, 0..30, [4; 16]);
+        let groups = sort_grouped(&x, Ascending).unwrap();
+        assert_eq!(groups.len(), 30);
+        let mut all: Vec<usize> = groups.iter().flat_map(|(_, v)| v.clone()).collect();
+        assert!(groups.iter().all(|(value, v)| v.iter().all(|&i| x[i] == *value)));
+        all.sort();
+        assert_eq!(all, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn argsort_u32_indices() {
+        let x = new_u32_vec(1024);
+        let indices = argsort_as::<u32, _, _>(&x, &|a, b| a.cmp(b)).unwrap();
+        assert_eq!(indices.len(), 1024);
+        let permuted: Vec<u32> = indices.iter().map(|&i| x[i as usize]).collect();
+        assert!(is_sorted_ascending(&permuted));
+        // usizeのインデックスと同じ並び順になる
+        let expected = argsort(&x, &|a, b| a.cmp(b)).unwrap();
+        assert!(indices.iter().zip(&expected).all(|(&i, &j)| i as usize == j));
+
+        // u16では65536要素のインデックスを表せない
+        let x = vec![0u8; 65536];
+        assert_eq!(
+            argsort_as::<u16, _, _>(&x, &|a, b| a.cmp(b)),
+            Err(SortError::TooLarge { len: 65536 })
+        );
+    }
+
+    #[test]
+    fn argsort_matches_sort_by() {
+        let x = new_u32_vec(1000);
+        let indices = argsort(&x, &|a, b| b.cmp(a)).unwrap();
+
+        // インデックスの順にxの要素を並べると、sort_byの結果と一致する
+        let permuted: Vec<u32> = indices.iter().map(|&i| x[i]).collect();
+        let mut expected = x.clone();
+        assert_eq!(sort_by(&mut expected, &|a, b| b.cmp(a)), Ok(()));
+        assert_eq!(permuted, expected);
+    }
+
+    #[test]
+    fn sort_by_counted_comparisons() {
+        // n = 8なら (8 / 4) * 3 * 4 = 24回、n = 16なら (16 / 4) * 4 * 5 = 80回比較する
+        let mut x = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        let stats = sort_by_counted(&mut x, &|a, b| a.cmp(b)).unwrap();
+        assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
+        assert_eq!(stats.comparisons, 24);
+        assert!(stats.swaps <= stats.comparisons);
+
+        let mut x = new_u32_vec(16);
+        let stats = sort_by_counted(&mut x, &|a, b| a.cmp(b)).unwrap();
+        assert!(is_sorted_ascending(&x));
+        assert_eq!(stats.comparisons, 80);
+    }
+
+    #[test]
+    fn sort_by_counted_equals() {
+        // 要素がすべて異なれば、Equalになる比較はない
+        let mut x = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(sort_by_counted(&mut x, &|a, b| a.cmp(b)).unwrap().equals, 0);
+
+        // 常にEqualを返すcomparatorでは、すべての比較がEqualになり交換は起こらない
+        let mut x = new_u32_vec(16);
+        let stats = sort_by_counted(&mut x, &|_, _| std::cmp::Ordering::Equal).unwrap();
+        assert_eq!(stats.equals, stats.comparisons);
+        assert_eq!(stats.swaps, 0);
+    }
+
+    #[test]
+    fn sort_by_reporting_swaps() {
+        let mut x: Vec<u32> = (0..1000).collect();
+        assert_eq!(sort_by_reporting(&mut x, &|a, b| a.cmp(b)), Ok(false));
+
+        let mut x = new_u32_vec(1000);
+        assert_eq!(sort_by_reporting(&mut x, &|a, b| a.cmp(b)), Ok(true));
+        // 一度ソートした後は交換が起きない
+        assert_eq!(sort_by_reporting(&mut x, &|a, b| a.cmp(b)), Ok(false));
+
+        // 挿入ソートで並べる小さなスライスでも、交換が起きたことがわかる
+        let mut x = vec![3, 1, 2];
+        assert_eq!(sort_by_reporting(&mut x, &|a, b| a.cmp(b)), Ok(true));
+        assert_eq!(x, vec![1, 2, 3]);
+
+        // ソート済みかの確認を除けば、比較の回数はsort_byと同じ（挿入ソートも使われる）
+        let calls = AtomicUsize::new(0);
+        let comparator = |a: &u32, b: &u32| {
+            calls.fetch_add(1, AtomicOrdering::Relaxed);
+            a.cmp(b)
+        };
+        let original = new_u32_vec(1000);
+        let mut x = original.clone();
+        assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+        let sort_calls = calls.swap(0, AtomicOrdering::Relaxed);
+        assert!(!is_sorted_by(&original, &comparator));
+        let check_calls = calls.swap(0, AtomicOrdering::Relaxed);
+        let mut x = original.clone();
+        assert_eq!(sort_by_reporting(&mut x, &comparator), Ok(true));
+        assert_eq!(calls.load(AtomicOrdering::Relaxed), check_calls + sort_calls);
+    }
+
+    #[test]
+    fn sort_by_if_skips_when_predicate_false() {
+        let original = new_u32_vec(1000);
+        let mut x = original.clone();
+        assert_eq!(sort_by_if(&mut x, &|a, b| a.cmp(b), |x| x.len() > 1000), Ok(false));
+        assert_eq!(x, original);
+
+        assert_eq!(sort_by_if(&mut x, &|a, b| a.cmp(b), |x| x.len() >= 1000), Ok(true));
+        assert!(is_sorted_ascending(&x));
+    }
+
+    // comparator_countはコンパイル時に評価できる。値が違えばコンパイルエラーになる
+    const _: () = assert!(comparator_count(8) == 24);
+
+    #[test]
+    fn comparator_count_matches_counted_sort() {
+        assert_eq!(comparator_count(0), 0);
+        assert_eq!(comparator_count(1), 0);
+        assert_eq!(comparator_count(12), 0);
+        for &n in &[2, 16, 1024] {
+            let mut x = new_u32_vec(n);
+            let stats = sort_by_counted(&mut x, &|a, b| a.cmp(b)).unwrap();
+            assert_eq!(stats.comparisons, comparator_count(n) as u64);
+        }
+        // 定数の文脈で配列の長さに使える
+        let buffer = [0u8; comparator_count(16)];
+        assert_eq!(buffer.len(), 80);
+    }
 
-        // ソート対象のベクタを作成する
-        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+    #[test]
+    fn sort_by_counted_parallel() {
+        // 並列に処理されても回数は失われない（65,536 / 4 * 16 * 17）
+        let mut x = new_u32_vec(65536);
+        let stats = sort_by_counted(&mut x, &|a, b| a.cmp(b)).unwrap();
+        assert!(is_sorted_ascending(&x));
+        assert_eq!(stats.comparisons, 4_456_448);
+    }
 
-        // ソート後の期待値を作成する
-        let expected = vec![&hanako, &kyoko, &taro, &ryosuke];
+    #[test]
+    fn parallel_compare_and_swap_matches_sequential() {
+        // 2^18要素なら、最初のcompare_and_swapは2^17組を並列イテレータで比較する
+        let x = new_u32_vec_in_range(1 << 18, 0..1000, [7; 16]);
+        let mut results = Vec::new();
+        for &threshold in &[PARALLEL_THRESHOLD, usize::MAX] {
+            assert_eq!(
+                Context::new(threshold).parallel_compare(1 << 17),
+                cfg!(feature = "parallel") && threshold != usize::MAX
+            );
+            let mut v = x.clone();
+            let counters = Counters::default();
+            let ctx = Context {
+                counters: Some(&counters),
+                ..Context::new(threshold)
+            };
+            do_sort(&mut v, true, &|a: &u32, b: &u32| a.cmp(b), &ctx);
+            assert!(is_sorted_ascending(&v));
+            results.push((v, counters.stats()));
+        }
+        // 比較の組ごとの処理は並列でも変わらないので、結果も回数も一致する
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test]
+    fn bitonic_config_stable_with_threshold() {
+        // キーが同じ要素が多いので、安定でなければ元の位置の順序が崩れる
+        let keys = new_u32_vec_in_range(3000, 0..10, [3; 16]);
+        let mut x: Vec<(u32, usize)> = keys.into_iter().zip(0..).collect();
+        let config = BitonicConfig::new().threshold(256).stable(true);
+        assert_eq!(config.sort_by(&mut x, &|a, b| a.0.cmp(&b.0)), Ok(()));
+        assert!(is_sorted_by(&x, &|a, b| a.cmp(b)));
 
+        // padをfalseにすると、2のべき乗でない要素数は受け付けない
+        let config = config.pad(false);
         assert_eq!(
-            // sort_by関数でソートする。第荷引数はソート順を決めるクロージャ
-            // 引数に2つのStudent構造体を取り、ageフィールドの値をcmpメソッドで
-            // 比較することで大小を決定する
-            sort_by(&mut x, &|a, b| a.age.cmp(&b.age)),
-            Ok(())
+            config.sort_by(&mut x, &|a, b| a.0.cmp(&b.0)),
+            Err(SortError::NotPowerOfTwo { len: 3000 })
         );
+        let mut y = new_u32_vec(1024);
+        assert_eq!(config.stable(false).sort_by(&mut y, &|a, b| a.cmp(b)), Ok(()));
+        assert!(is_sorted_ascending(&y));
+    }
+
+    #[test]
+    fn sort_prefix_leaves_tail() {
+        let mut x = vec![9, 3, 7, 1, 8, 2, 6, 4, 0, 5];
+        assert_eq!(sort_prefix_by(&mut x, &|a, b| a.cmp(b)), Ok(8));
+        assert_eq!(&x[..8], &[1, 2, 3, 4, 6, 7, 8, 9]);
+        assert_eq!(&x[8..], &[0, 5]);
 
-        assert_eq!(x, expected)
+        let mut x = vec![2, 1, 0, 3];
+        assert_eq!(sort_prefix_by(&mut x, &|a, b| a.cmp(b)), Ok(4));
+        assert_eq!(x, vec![0, 1, 2, 3]);
+        let mut x: Vec<u32> = Vec::new();
+        assert_eq!(sort_prefix_by(&mut x, &|a, b| a.cmp(b)), Ok(0));
     }
 
     #[test]
-    fn sort_student_by_name_ascending() {
-        let taro = Student::new("Taro", "Yamada", 16);
-        let hanako = Student::new("Hanako", "Yamada", 16);
-        let kyoko = Student::new("Kyoko", "Ito", 16);
-        let ryosuke = Student::new("Ryosuke", "Hayashi", 16);
+    fn sort_range_only() {
+        let mut x = vec![8, 7, 6, 5, 4, 3, 2, 1];
+        assert_eq!(sort_range_by(&mut x, 2..6, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(x, vec![8, 7, 3, 4, 5, 6, 2, 1]);
+        // 2のべき乗でない長さの範囲もソートできる
+        assert_eq!(sort_range_by(&mut x, 0..3, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(x, vec![3, 7, 8, 4, 5, 6, 2, 1]);
 
-        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
-        let expected = vec![&ryosuke, &kyoko, &hanako, &taro];
+        assert_eq!(
+            sort_range_by(&mut x, 4..9, &|a, b| a.cmp(b)),
+            Err(SortError::InvalidRange { len: 8, start: 4, end: 9 })
+        );
+        assert_eq!(
+            sort_range_by(&mut x, Range { start: 5, end: 3 }, &|a, b| a.cmp(b)),
+            Err(SortError::InvalidRange { len: 8, start: 5, end: 3 })
+        );
+    }
 
+    #[test]
+    fn sort_valid_region() {
+        // 末尾の3要素は使われていない領域
+        let mut x = [7, 2, 9, 4, 1, 0, 0, 0];
+        assert_eq!(sort_valid_by(&mut x, 5, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(x, [1, 2, 4, 7, 9, 0, 0, 0]);
+
+        assert_eq!(sort_valid_by(&mut x, 8, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(x, [0, 0, 0, 1, 2, 4, 7, 9]);
         assert_eq!(
-            sort_by(
-                &mut x,
-                // まずlast_nameを比較する
-                &|a, b| a
-                    .last_name
-                    .cmp(&b.last_name)
-                    // もしlast_nameが等しくない（LessまたはGreater）ならそれを返す
-                    // last_nameが等しい（Equal）ならfirst_nameを比較する
-                    .then_with(|| a.first_name.cmp(&b.first_name))
-            ),
-            Ok(())
+            sort_valid_by(&mut x, 9, &|a, b| a.cmp(b)),
+            Err(SortError::ValidLenOutOfRange { len: 8, valid_len: 9 })
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn sort_by_catch_unwind_reports_panic() {
+        // 10000を比較しようとするとパニックするcomparator
+        let comparator = |a: &u32, b: &u32| {
+            if *a == 10000 || *b == 10000 {
+                panic!("found the sentinel");
+            }
+            a.cmp(b)
+        };
+        let mut x = new_u32_vec_in_range(65536, 0..1000, [2; 16]);
+        assert_eq!(sort_by_catch_unwind(&mut x, &comparator), Ok(()));
+        assert!(is_sorted_ascending(&x));
+
+        x[30000] = 10000;
+        assert_eq!(sort_by_catch_unwind(&mut x, &comparator), Err(SortError::ComparatorPanicked));
+        // パニックしても要素が失われることはない
+        assert_eq!(x.iter().filter(|&&v| v == 10000).count(), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn sort_by_timed_measures() {
+        let mut x = new_u32_vec(65536);
+        let elapsed = sort_by_timed(&mut x, &|a, b| a.cmp(b)).unwrap();
+        assert!(is_sorted_ascending(&x));
+        assert!(elapsed.as_nanos() > 0);
+    }
+
+    #[test]
+    fn sort_u32_radix_hybrid_matches_sort() {
+        // 上位8ビットが同じ値の多いデータと、ばらばらなデータの両方で確かめる
+        let narrow = new_u32_vec_in_range(5000, 0x1200_0000..0x1200_1000, [5; 16]);
+        for x in &[new_u32_vec(10000), narrow, vec![], vec![7]] {
+            for &order in &[Ascending, Descending] {
+                let mut expected = x.clone();
+                assert_eq!(sort(&mut expected, order), Ok(()));
+                let mut y = x.clone();
+                assert_eq!(sort_u32_radix_hybrid(&mut y, order), Ok(()));
+                assert_eq!(y, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_by_trace_snapshots() {
+        // 4要素なら段数は2 * 3 / 2 = 3
+        // 1段目で2要素ずつ、2段目で折り返して比べ、3段目で隣り合う要素を比べる
+        let mut x = vec![3, 1, 4, 2];
+        let snapshots = sort_by_trace(&mut x, &|a, b| a.cmp(b)).unwrap();
+        assert_eq!(
+            snapshots,
+            vec![vec![1, 3, 2, 4], vec![1, 2, 3, 4], vec![1, 2, 3, 4]]
+        );
+        assert_eq!(x, vec![1, 2, 3, 4]);
+
+        // 段数はsort_by_with_progressが知らせる全体の段数と同じ
+        let mut x = new_u32_vec(100);
+        let mut total = 0;
+        let mut y = x.clone();
+        sort_by_with_progress(&mut y, &|a, b| a.cmp(b), |_, t| total = t).unwrap();
+        let snapshots = sort_by_trace(&mut x, &|a, b| a.cmp(b)).unwrap();
+        assert_eq!(snapshots.len(), total);
+        assert_eq!(snapshots.last(), Some(&y));
+    }
+
+    // デバッグビルドでは、各マージの前にバイトニック列かを確かめるdebug_assert!が実行される
+    // 正しいソートではパニックしないことを、要素数と並列に処理するしきい値を変えて確かめる
+    #[cfg(debug_assertions)]
+    #[test]
+    fn debug_bitonic_checks_pass() {
+        for &len in &[2, 17, 100, 1000, 4096, 5000] {
+            let x = new_u32_vec_in_range(len, 0..100, [len as u8; 16]);
+            for &threshold in &[0, 64, usize::MAX] {
+                for &order in &[Ascending, Descending] {
+                    let mut y = x.clone();
+                    assert_eq!(sort_with_threshold(&mut y, order, threshold), Ok(()));
+                }
+            }
+        }
+    }
+
+    // partial_cmpはNaNとの比較でNoneを返すので、sort_by_partialの結果は規定しない
+    // その場合もバイトニック列かの確認でパニックせず、要素が失われないことを確かめる
+    #[test]
+    fn sort_by_partial_nan_does_not_panic() {
+        let mut x: Vec<f64> = new_u32_vec(64)
+            .into_iter()
+            .map(|v| if v % 5 == 0 { f64::NAN } else { f64::from(v % 1000) })
+            .collect();
+        assert!(x.iter().any(|v| v.is_nan()));
+        let mut expected: Vec<u64> = x.iter().map(|v| v.to_bits()).collect();
+        assert_eq!(sort_by_partial(&mut x, &|a: &f64, b: &f64| a.partial_cmp(b)), Ok(()));
+        let mut bits: Vec<u64> = x.iter().map(|v| v.to_bits()).collect();
+        bits.sort();
+        expected.sort();
+        assert_eq!(bits, expected);
+    }
+
+    #[test]
+    fn sort_without_padding_slots() {
+        // CloneもSentinelも実装していない型でも、2のべき乗でない要素数のままソートできる
+        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct NoClone(u32);
+
+        let mut x: Vec<NoClone> = new_u32_vec(1000).into_iter().map(NoClone).collect();
+        let capacity = x.capacity();
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert!(is_sorted_descending(&x));
+        // xはスライスとして渡すので、要素数も容量も変わらない
+        assert_eq!((x.len(), x.capacity()), (1000, capacity));
+    }
+
+    #[test]
+    fn small_sort_matches_std_sort() {
+        // 挿入ソートに切り替わる要素数の前後と、それを含む大きな要素数で確かめる
+        for len in (0..=40).chain([255, 1000, 4096]) {
+            let x = new_u32_vec_in_range(len, 0..20, [len as u8; 16]);
+            for &order in &[Ascending, Descending] {
+                let mut expected = x.clone();
+                expected.sort();
+                if order == Descending {
+                    expected.reverse();
+                }
+                let mut y = x.clone();
+                assert_eq!(sort(&mut y, order), Ok(()));
+                assert_eq!(y, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_f64_partial_cmp() {
+        let mut x: Vec<f64> = new_vec::<f64>(1000).iter().map(|v| v * 2.0 - 1.0).collect();
+        assert_eq!(sort_by_partial(&mut x, &|a: &f64, b: &f64| a.partial_cmp(b)), Ok(()));
+        assert!(x.windows(2).all(|pair| pair[0] <= pair[1]));
+        // partial_cmpをそのまま渡せる
+        let mut y = vec![2.5, -1.0, 0.5];
+        assert_eq!(sort_by_partial(&mut y, &f64::partial_cmp), Ok(()));
+        assert_eq!(y, vec![-1.0, 0.5, 2.5]);
+    }
+
+    #[test]
+    fn sort_f64_total() {
+        let mut x = vec![2.5, f64::NAN, -1.0, 0.0, f64::INFINITY, -0.0, f64::NAN, 1.5];
+        assert_eq!(sort_by_float_total(&mut x, Ascending), Ok(()));
+        // NaNは末尾にまとめられ、残りは昇順に並ぶ
+        assert!(x[6].is_nan() && x[7].is_nan());
+        assert_eq!(&x[..6], &[-1.0, -0.0, 0.0, 1.5, 2.5, f64::INFINITY]);
+        // -0.0と0.0は==では区別できないので符号を確認する
+        assert!(x[1].is_sign_negative() && x[2].is_sign_positive());
+
+        assert_eq!(sort_by_float_total(&mut x, Descending), Ok(()));
+        assert!(x[0].is_nan() && x[1].is_nan());
+        assert_eq!(&x[2..], &[f64::INFINITY, 2.5, 1.5, 0.0, -0.0, -1.0]);
+    }
+
+    #[test]
+    fn sort_iterative_matches_sort_by() {
+        for &len in &[0, 1, 2, 3, 8, 100, 1000, 1024, 4096] {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            assert_eq!(sort_by(&mut expected, &|a, b| a.cmp(b)), Ok(()));
+            assert_eq!(sort_iterative(&mut x, &|a, b| a.cmp(b)), Ok(()));
+            assert_eq!(x, expected, "len: {}", len);
+
+            assert_eq!(sort_by(&mut expected, &|a, b| b.cmp(a)), Ok(()));
+            assert_eq!(sort_iterative(&mut x, &|a, b| b.cmp(a)), Ok(()));
+            assert_eq!(x, expected, "len: {}", len);
+        }
+    }
+
+    #[test]
+    fn partial_sort_by_smallest_k() {
+        for &(len, k) in &[(1000, 10), (1000, 1), (1024, 64), (100, 33), (7, 5), (5, 8)] {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            assert_eq!(partial_sort_by(&mut x, k, &|a, b| a.cmp(b)), Ok(()));
+            let k = k.min(len);
+            // 先頭のk要素は、全体をソートした結果の先頭k要素と一致する
+            assert_eq!(&x[..k], &expected[..k], "len: {}, k: {}", len, k);
+            // 残りの要素も失われていない
+            x.sort();
+            assert_eq!(x, expected);
+        }
+
+        // 2のべき乗に切り上げるとあふれるkでも、全体をソートする
+        let mut x = vec![3, 1, 2];
+        assert_eq!(partial_sort_by(&mut x, usize::MAX, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(x, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn sort_by_with_progress_stages() {
+        for &(len, total) in &[(1024, 55), (1000, 55), (65536, 136), (2, 1), (1, 0)] {
+            let mut x = new_u32_vec(len);
+            let mut calls = Vec::new();
+            let result = sort_by_with_progress(&mut x, &|a, b| a.cmp(b), |completed, total| {
+                calls.push((completed, total))
+            });
+            assert_eq!(result, Ok(()));
+            assert!(is_sorted_ascending(&x), "len: {}", len);
+
+            // 段が終わるたびに1回ずつ、完了した段数を増やしながら呼ばれる
+            let expected: Vec<_> = (1..=total).map(|completed| (completed, total)).collect();
+            assert_eq!(calls, expected, "len: {}", len);
+        }
+    }
+
+    #[test]
+    fn sort_by_cancellable_cancelled() {
+        let cancel = AtomicBool::new(false);
+        let started = AtomicBool::new(false);
+        let mut x = new_u32_vec(65536);
+
+        std::thread::scope(|s| {
+            // ソートが始まったら別のスレッドから中断を要求する
+            s.spawn(|| {
+                while !started.load(AtomicOrdering::SeqCst) {
+                    std::thread::yield_now();
+                }
+                cancel.store(true, AtomicOrdering::SeqCst);
+            });
+
+            let comparator = |a: &u32, b: &u32| {
+                // 中断が要求されるまで比較を進めない
+                started.store(true, AtomicOrdering::SeqCst);
+                while !cancel.load(AtomicOrdering::SeqCst) {
+                    std::thread::yield_now();
+                }
+                a.cmp(b)
+            };
+            assert_eq!(
+                sort_by_cancellable(&mut x, &comparator, &cancel),
+                Err(SortError::Cancelled)
+            );
+        });
+    }
+
+    #[test]
+    fn sort_by_cancellable_skips_leaves_after_cancel() {
+        // 最初の比較で中断を要求する。挿入ソートで並べる小さな区間も、
+        // 中断された後は並べないので、最初の区間より後ろの要素は動かない
+        let cancel = AtomicBool::new(false);
+        let comparator = |a: &u32, b: &u32| {
+            cancel.store(true, AtomicOrdering::Relaxed);
+            a.cmp(b)
+        };
+        let original = new_u32_vec(64);
+        let mut x = original.clone();
+        assert_eq!(
+            sort_by_cancellable(&mut x, &comparator, &cancel),
+            Err(SortError::Cancelled)
+        );
+        assert_eq!(&x[16..], &original[16..]);
+
+        // 始める前に中断が要求されていれば、小さなスライスも並べない
+        let mut x = vec![3, 1, 2];
+        assert_eq!(
+            sort_by_cancellable(&mut x, &|a, b| a.cmp(b), &cancel),
+            Err(SortError::Cancelled)
+        );
+        assert_eq!(x, vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn sort_by_cancellable_not_cancelled() {
+        let cancel = AtomicBool::new(false);
+        let mut x = new_u32_vec(1000);
+        assert_eq!(sort_by_cancellable(&mut x, &|a, b| a.cmp(b), &cancel), Ok(()));
+        assert!(is_sorted_ascending(&x));
+    }
+
+    #[test]
+    fn sort_by_yielding_calls_yield() {
+        let comparisons = AtomicUsize::new(0);
+        let comparator = |a: &u32, b: &u32| {
+            comparisons.fetch_add(1, AtomicOrdering::Relaxed);
+            a.cmp(b)
+        };
+        let yields = AtomicUsize::new(0);
+        let yield_now = || {
+            yields.fetch_add(1, AtomicOrdering::Relaxed);
+        };
+
+        let mut x = new_u32_vec(1000);
+        assert_eq!(sort_by_yielding(&mut x, &comparator, 100, yield_now), Ok(()));
+        assert!(is_sorted_ascending(&x));
+        let comparisons = comparisons.load(AtomicOrdering::Relaxed);
+        assert!(comparisons >= 1000);
+        assert_eq!(yields.load(AtomicOrdering::Relaxed), comparisons / 100);
+
+        // yield_everyが0ならyield_nowは呼ばれない
+        yields.store(0, AtomicOrdering::Relaxed);
+        let mut x = new_u32_vec(1000);
+        assert_eq!(sort_by_yielding(&mut x, &comparator, 0, yield_now), Ok(()));
+        assert_eq!(yields.load(AtomicOrdering::Relaxed), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn try_sort_by_propagates_error() {
+        // u32::MAXを比較しようとするとエラーになるcomparator
+        let comparator = |a: &u32, b: &u32| {
+            if *a == u32::MAX || *b == u32::MAX {
+                Err("sentinel")
+            } else {
+                Ok(a.cmp(b))
+            }
+        };
+
+        let mut x = new_u32_vec(1000);
+        x[123] = u32::MAX;
+        let mut before = x.clone();
+        assert_eq!(try_sort_by(&mut x, &comparator), Err(TrySortError::Comparator("sentinel")));
+        // 失敗しても要素は失われない
+        before.sort();
+        x.sort();
+        assert_eq!(x, before);
+
+        // エラーにならなければ通常のソートと同じ
+        let mut x = new_u32_vec(1000);
+        assert_eq!(try_sort_by(&mut x, &comparator), Ok(()));
+        assert!(is_sorted_ascending(&x));
+    }
+
+    #[test]
+    fn sorted_keeps_input() {
+        let x = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(sorted(&x, Ascending), Ok(vec![4, 10, 11, 20, 21, 30, 110, 330]));
+        assert_eq!(
+            sorted_by(&x, &|a, b| b.cmp(a)),
+            Ok(vec![330, 110, 30, 21, 20, 11, 10, 4])
+        );
+        // 元のスライスは変更されない
+        assert_eq!(x, vec![10, 30, 11, 20, 4, 330, 21, 110]);
+    }
+
+    #[test]
+    fn median_by_lower_median() {
+        // 要素数が偶数なら、中央の2つ（20と30）のうち小さい方を返す
+        assert_eq!(median_by(&[30, 10, 20, 40], &|a, b| a.cmp(b)), Ok(20));
+        assert_eq!(median_by(&[30, 10, 20], &|a, b| a.cmp(b)), Ok(20));
+        assert_eq!(median_by(&[7], &|a, b| a.cmp(b)), Ok(7));
+        // 降順のcomparatorでは、中央の2つのうちcomparatorの順序で前にある30を返す
+        assert_eq!(median_by(&[30, 10, 20, 40], &|a, b| b.cmp(a)), Ok(30));
+
+        let x: [u32; 0] = [];
+        assert_eq!(median_by(&x, &|a, b| a.cmp(b)), Err(SortError::Empty));
+    }
+
+    #[test]
+    fn sort_both_returns_reverses() {
+        // 重複した要素を含むデータ
+        let x = vec![3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5];
+        let (ascending, descending) = sort_both(&x).unwrap();
+        assert_eq!(ascending, vec![1, 1, 2, 3, 3, 4, 5, 5, 5, 6, 9]);
+        assert_eq!(descending, vec![9, 6, 5, 5, 5, 4, 3, 3, 2, 1, 1]);
+        assert_eq!(descending, sorted(&x, Descending).unwrap());
+        assert_eq!(x, vec![3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5]);
+    }
+
+    #[test]
+    fn sort_with_extension_methods() {
+        let mut x = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(
+            x.bitonic_sort(Ascending).map(|s| s.to_vec()),
+            Ok(vec![4, 10, 11, 20, 21, 30, 110, 330])
         );
+
+        // ソートしたスライスがそのまま返るので、続けてイテレータの処理を書ける
+        let total: u32 = x
+            .bitonic_sort_by(&|a, b| b.cmp(a))
+            .unwrap()
+            .iter()
+            .take(3)
+            .sum();
+        assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
+        assert_eq!(total, 470);
+    }
+
+    #[test]
+    fn sort_dedup_removes_repeats() {
+        let mut x = vec![3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5];
+        assert_eq!(sort_dedup(&mut x, Ascending), Ok(()));
+        assert_eq!(x, vec![1, 2, 3, 4, 5, 6, 9]);
+
+        let mut x = new_u32_vec_in_range(1000, 0..10, [0; 16]);
+        assert_eq!(sort_dedup(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn sort_vec_and_iterator() {
+        let mut x = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(sort_vec(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
+
+        // イテレータから直接ソート済みのベクタを作る
+        let words = "Rust is fast and memory-efficient with no GC";
+        let sorted_words = sort_into_vec(words.split_whitespace(), &|a, b| a.len().cmp(&b.len()))
+            .unwrap();
+        assert!(is_sorted_by(&sorted_words, &|a, b| a.len().cmp(&b.len())));
+        assert_eq!(sorted_words.len(), 8);
+    }
+
+    #[test]
+    fn bitonic_sorter_reuses_buffer() {
+        let mut sorter = BitonicSorter::new();
+        let mut capacity = sorter.capacity();
+        for i in 0..10_000u32 {
+            // 長さが1〜16の小さなスライスを繰り返しソートする
+            let x = new_u32_vec_seeded((i % 16 + 1) as usize, [(i % 256) as u8; 16]);
+            let sorted = sorter.sorted_by(&x, &|a, b| a.cmp(b)).unwrap();
+            assert!(is_sorted_ascending(sorted));
+            assert_eq!(sorted.len(), x.len());
+
+            // バッファの容量は大きくなることはあっても小さくなることはない
+            assert!(sorter.capacity() >= capacity);
+            capacity = sorter.capacity();
+        }
+        assert!(capacity <= 32);
+
+        let mut x = vec![10, 30, 11, 20, 4];
+        assert_eq!(sorter.sort_by(&mut x, &|a, b| b.cmp(a)), Ok(()));
+        assert_eq!(x, vec![30, 20, 11, 10, 4]);
+
+        // その場でのソートはバッファを使わないので、大きなスライスでも容量は変わらない
+        let mut x = new_u32_vec(1000);
+        assert_eq!(sorter.sort_by(&mut x, &|a, b| a.cmp(b)), Ok(()));
+        assert!(is_sorted_ascending(&x));
+        assert_eq!(sorter.capacity(), capacity);
+    }
+
+    #[test]
+    fn sort_array_fixed_size() {
+        let mut x = [10, 30, 11, 20];
+        sort_array(&mut x, Ascending);
+        assert_eq!(x, [10, 11, 20, 30]);
+
+        let mut x = [10, 30, 11, 20, 4, 330, 21, 110];
+        sort_array(&mut x, Descending);
+        assert_eq!(x, [330, 110, 30, 21, 20, 11, 10, 4]);
+    }
+
+    #[test]
+    fn sort_by_stable_keeps_order_of_equal_keys() {
+        // (キー, タグ)の組をキーだけで比較してソートする
+        let mut x = vec![(3, 'a'), (1, 'b'), (3, 'c'), (2, 'd'), (1, 'e'), (3, 'f'), (2, 'g')];
+        assert_eq!(sort_by_stable(&mut x, &|a, b| a.0.cmp(&b.0)), Ok(()));
+        // キーが等しい要素の間では、タグが元の順序のまま並ぶ
+        assert_eq!(
+            x,
+            vec![(1, 'b'), (1, 'e'), (2, 'd'), (2, 'g'), (3, 'a'), (3, 'c'), (3, 'f')]
+        );
+
+        // 重複の多い大きな入力でも、標準ライブラリの安定ソートと同じ結果になる
+        let mut x: Vec<(u32, usize)> = new_u32_vec(1000)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (v % 10, i))
+            .collect();
+        let mut expected = x.clone();
+        expected.sort_by_key(|&(key, _)| key);
+        assert_eq!(sort_by_stable(&mut x, &|a, b| a.0.cmp(&b.0)), Ok(()));
         assert_eq!(x, expected);
     }
 
     #[test]
-    fn sort_u32_large() {
-        {
-            // 乱数で65,536要素のデータ列を作る（65,536は2の16乗）
-            let mut x = new_u32_vec(65536);
-            // 昇順にソートする
-            assert_eq!(sort(&mut x, &Ascending), Ok(()));
-            assert!(is_sorted_ascending(&x));
+    fn sort_by_adaptive_skips_sorted_input() {
+        let comparisons = AtomicUsize::new(0);
+        let comparator = |a: &u32, b: &u32| {
+            comparisons.fetch_add(1, AtomicOrdering::Relaxed);
+            a.cmp(b)
+        };
+
+        // ソート済みの入力では、確認のためのn - 1回しか比較しない
+        let mut x: Vec<u32> = (0..1024).collect();
+        assert_eq!(sort_by_adaptive(&mut x, &comparator), Ok(()));
+        assert_eq!(comparisons.load(AtomicOrdering::Relaxed), 1023);
+        assert_eq!(x, (0..1024).collect::<Vec<u32>>());
+        // バイトニックソートのネットワークはソート済みの入力でも要素を交換する
+        assert!(sort_by_counted(&mut x, &|a, b| a.cmp(b)).unwrap().swaps > 0);
+
+        // ソート済みでなければ、通常どおりソートする
+        let mut x = new_u32_vec(1024);
+        assert_eq!(sort_by_adaptive(&mut x, &comparator), Ok(()));
+        assert!(is_sorted_ascending(&x));
+    }
+
+    #[test]
+    fn bitonic_merge_sorted_halves() {
+        // 前半が昇順、後半が降順のバイトニック列
+        let mut x = vec![1, 4, 6, 8, 7, 5, 3, 2];
+        assert_eq!(bitonic_merge(&mut x, true, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(x, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let mut x = vec![1, 4, 6, 8, 7, 5, 3, 2];
+        assert_eq!(bitonic_merge(&mut x, false, &|a, b| a.cmp(b)), Ok(()));
+        assert_eq!(x, vec![8, 7, 6, 5, 4, 3, 2, 1]);
+
+        // ソート済みの2つの列を、後ろだけ逆順にしてつなげてからマージする
+        let mut first = new_u32_vec_seeded(512, [1; 16]);
+        let mut second = new_u32_vec_seeded(512, [2; 16]);
+        first.sort();
+        second.sort();
+        second.reverse();
+        let mut x = [first, second].concat();
+        assert_eq!(bitonic_merge(&mut x, true, &|a, b| a.cmp(b)), Ok(()));
+        assert!(is_sorted_ascending(&x));
+
+        let mut x = vec![1, 3, 2];
+        assert_eq!(
+            bitonic_merge(&mut x, true, &|a, b| a.cmp(b)),
+            Err(SortError::NotPowerOfTwo { len: 3 })
+        );
+
+        // 空のスライスはエラーにならない
+        let mut x: Vec<u32> = vec![];
+        assert_eq!(bitonic_merge(&mut x, true, &|a, b| a.cmp(b)), Ok(()));
+    }
+
+    #[test]
+    fn bitonic_merge_ordered_both_directions() {
+        let mut x = vec![2, 5, 7, 9, 8, 6, 4, 1];
+        assert_eq!(bitonic_merge_ordered(&mut x, Ascending), Ok(()));
+        assert_eq!(x, vec![1, 2, 4, 5, 6, 7, 8, 9]);
+
+        let mut x = vec![2, 5, 7, 9, 8, 6, 4, 1];
+        assert_eq!(bitonic_merge_ordered(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![9, 8, 7, 6, 5, 4, 2, 1]);
+
+        let mut x = vec![1, 3, 2];
+        assert_eq!(
+            bitonic_merge_ordered(&mut x, Ascending),
+            Err(SortError::NotPowerOfTwo { len: 3 })
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn tune_threshold_returns_candidate() {
+        let sample = new_u32_vec(20000);
+        assert!(TUNE_CANDIDATES.contains(&tune_threshold(&sample)));
+    }
+
+    #[cfg(all(feature = "scoped", not(feature = "parallel")))]
+    #[test]
+    fn sort_u32_scoped_threads() {
+        // しきい値以上の区間は、std::thread::scopeで作ったスレッドで並列にソートされる
+        let mut x = new_u32_vec(65536);
+        let mut expected = x.clone();
+        expected.sort();
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(x, expected);
+    }
+
+    #[cfg(all(feature = "scoped", not(feature = "parallel")))]
+    #[test]
+    fn scoped_threads_are_bounded() {
+        use super::default_parallel_depth;
+
+        // 同時に動くスレッドは2^default_parallel_depth()個までで、CPUの数の2倍未満になる
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let max_threads = 1 << default_parallel_depth();
+        assert!(max_threads >= cpus && max_threads < cpus * 2);
+
+        // 比較を同時に実行しているスレッドの数を数え、その最大値を記録する
+        // 段数を制限しないと、しきい値が小さいので数千のスレッドが同時に作られる
+        let active = AtomicUsize::new(0);
+        let max_active = AtomicUsize::new(0);
+        let comparator = |a: &u32, b: &u32| {
+            let n = active.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+            max_active.fetch_max(n, AtomicOrdering::SeqCst);
+            std::thread::yield_now();
+            active.fetch_sub(1, AtomicOrdering::SeqCst);
+            a.cmp(b)
+        };
+        let mut x = new_u32_vec(1 << 18);
+        assert_eq!(sort_by_with_threshold(&mut x, &comparator, 64), Ok(()));
+        assert!(is_sorted_ascending(&x));
+        assert!(max_active.load(AtomicOrdering::SeqCst) <= max_threads);
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn logging_traces_stages() {
+        use log::{LevelFilter, Log, Metadata, Record};
+        use std::cell::RefCell;
+
+        // 他のテストも同時にソートするので、このテストのスレッドで出力されたものだけを集める
+        thread_local! {
+            static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
         }
-        {
-            let mut x = new_u32_vec(65536);
-            assert_eq!(sort(&mut x, &Descending), Ok(()));
-            assert!(is_sorted_descending(&x));
+
+        struct TestLogger;
+
+        impl Log for TestLogger {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                CAPTURED.with(|c| {
+                    if let Some(captured) = c.borrow_mut().as_mut() {
+                        captured.push(record.args().to_string());
+                    }
+                });
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: TestLogger = TestLogger;
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+
+        // 順番にソートさせて、すべての出力がこのスレッドで行われるようにする
+        CAPTURED.with(|c| *c.borrow_mut() = Some(Vec::new()));
+        let mut x = new_u32_vec(64);
+        assert_eq!(sort_with_threshold(&mut x, Ascending, usize::MAX), Ok(()));
+        let captured = CAPTURED.with(|c| c.borrow_mut().take()).unwrap();
+
+        assert!(is_sorted_ascending(&x));
+        assert!(captured.contains(&"sort: halves of len 32 sorted sequentially".to_string()));
+        assert!(captured.contains(&"merge start: len 64, forward true".to_string()));
+        assert_eq!(captured.last().unwrap(), "merge end: len 64, forward true");
+    }
+
+    // proptestで作った様々な入力に対して、標準ライブラリのソートと結果が一致することを確かめる
+    // 比較ネットワークの誤りは特定の要素数や重複した値でしか現れないことがあるので、
+    // 要素数と値の範囲を変えた入力をたくさん試す
+    #[cfg(not(target_arch = "wasm32"))]
+    mod properties {
+        use super::super::{sort, sort_by, sort_by_stable, sort_with_threshold};
+        use crate::SortOrder::*;
+        use core::cmp::Ordering;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+
+        // 要素数が2^0から2^10までの、2のべき乗のベクタ
+        fn power_of_two_vec() -> impl Strategy<Value = Vec<u32>> {
+            (0..=10usize).prop_flat_map(|exp| vec(any::<u32>(), 1 << exp))
+        }
+
+        proptest! {
+            #[test]
+            fn sort_matches_std_sort(mut x in power_of_two_vec()) {
+                let mut expected = x.clone();
+                expected.sort();
+                prop_assert_eq!(sort(&mut x, Ascending), Ok(()));
+                prop_assert_eq!(&x, &expected);
+
+                expected.reverse();
+                prop_assert_eq!(sort(&mut x, Descending), Ok(()));
+                prop_assert_eq!(x, expected);
+            }
+
+            #[test]
+            fn sort_any_len_with_duplicates(mut x in vec(0..8u8, 0..300), threshold in 0..64usize) {
+                // 値の範囲を狭くして重複を増やし、しきい値を小さくして並列の経路も通す
+                let mut expected = x.clone();
+                expected.sort();
+                prop_assert_eq!(sort_with_threshold(&mut x, Ascending, threshold), Ok(()));
+                prop_assert_eq!(x, expected);
+            }
+
+            #[test]
+            fn sort_by_custom_comparator(mut x in vec((any::<u8>(), any::<u8>()), 0..300)) {
+                // 2番目の値の降順に並べる。等しい要素の順序は決まらないので、
+                // 並び方と、要素が失われたり重複したりしていないことを別々に確かめる
+                let comparator = |a: &(u8, u8), b: &(u8, u8)| b.1.cmp(&a.1);
+                let mut expected = x.clone();
+                prop_assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+                prop_assert!(x.windows(2).all(|w| comparator(&w[0], &w[1]) != Ordering::Greater));
+
+                x.sort();
+                expected.sort();
+                prop_assert_eq!(x, expected);
+            }
+
+            #[test]
+            fn sort_by_stable_matches_std_sort_by(mut x in vec(any::<u32>(), 0..300)) {
+                // 安定なソートなら、等しい要素の順序まで標準ライブラリの安定ソートと一致する
+                let comparator = |a: &u32, b: &u32| (a % 16).cmp(&(b % 16));
+                let mut expected = x.clone();
+                expected.sort_by(comparator);
+                prop_assert_eq!(sort_by_stable(&mut x, &comparator), Ok(()));
+                prop_assert_eq!(x, expected);
+            }
         }
     }
 }
@@ -1,12 +1,295 @@
+// stdフィーチャーを無効にすると、allocだけを使う#![no_std]のクレートになる
+// その場合も、スライスをソートする関数（並列処理を除く）はすべて使える
+// thumbv7em-none-eabiなどのターゲット向けにビルドする方法はREADME.mdを参照
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+pub mod cmp;
+pub mod direction;
 pub mod first;
 pub mod second;
 pub mod third;
 pub mod fourth;
+pub mod oddeven;
+#[cfg(feature = "simd")]
+pub mod simd;
 pub mod utils;
 
 // SortOrderを列挙型として定義する
+// Copyなので、関数には参照ではなく値で渡す
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SortOrder {
     Ascending, //　昇順
     Descending, // 降順
 }
 
+// "ascending"または"descending"と表示する。この文字列はFromStrでSortOrderに戻せる
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SortOrder::Ascending => write!(f, "ascending"),
+            SortOrder::Descending => write!(f, "descending"),
+        }
+    }
+}
+
+// "asc"、"ascending"、"desc"、"descending"をSortOrderに変換する。大文字と小文字は区別しない
+// コマンドライン引数からソートの順序を受け取る時などに使う
+impl FromStr for SortOrder {
+    type Err = ParseSortOrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is = |name: &str| s.eq_ignore_ascii_case(name);
+        if is("asc") || is("ascending") {
+            Ok(SortOrder::Ascending)
+        } else if is("desc") || is("descending") {
+            Ok(SortOrder::Descending)
+        } else {
+            Err(ParseSortOrderError {
+                input: s.to_string(),
+            })
+        }
+    }
+}
+
+// 文字列をSortOrderに変換できなかったことを表す。変換しようとした文字列を持つ
+#[derive(Debug, PartialEq)]
+pub struct ParseSortOrderError {
+    pub input: String,
+}
+
+impl fmt::Display for ParseSortOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid sort order: {:?}. (expected asc, ascending, desc or descending)",
+            self.input
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for ParseSortOrderError {}
+
+// 要素数を2のべき乗まで実際に埋める時に、番兵として使える値を持つ型
+// 番兵は昇順ならmax_value、降順ならmin_valueで、ソート後は必ず末尾に並ぶ
+// Stringのように最大値や最小値を持たない型には実装できない
+pub trait Sentinel {
+    fn max_value() -> Self;
+    fn min_value() -> Self;
+}
+
+macro_rules! impl_sentinel {
+    ($($t:ty),*) => {
+        $(
+            impl Sentinel for $t {
+                fn max_value() -> Self {
+                    <$t>::MAX
+                }
+
+                fn min_value() -> Self {
+                    <$t>::MIN
+                }
+            }
+        )*
+    };
+}
+
+impl_sentinel!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+// fourth::argsort_asが返すインデックスに使える整数型
+// 要素数が少なければu16やu32を使うことで、usizeよりインデックスの列のメモリを減らせる
+pub trait IndexInt: Copy + Send + Sync {
+    // usizeから変換する。この型で表せない値ならNoneを返す
+    fn from_usize(n: usize) -> Option<Self>;
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! impl_index_int {
+    ($($t:ty),*) => {
+        $(
+            impl IndexInt for $t {
+                fn from_usize(n: usize) -> Option<Self> {
+                    <$t>::try_from(n).ok()
+                }
+
+                fn to_usize(self) -> usize {
+                    // usizeから変換した値しか持たないので、切り捨てられることはない
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_index_int!(u16, u32, u64, usize);
+
+// 2つの要素の順序を決める比較関数を表すトレイト
+// 比較の方法に名前をつけた構造体を作れば、同じ比較を何度も使い回せる
+// Fn(&T, &T) -> Orderingを実装したクロージャや関数にも実装されている
+pub trait Comparator<T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+impl<T, F> Comparator<T> for F
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+// ソート中に行われた比較と交換の回数
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SortStats {
+    pub comparisons: u64, // 比較した回数
+    pub swaps: u64,       // 要素を交換した回数
+    pub equals: u64,      // 比較の結果がEqualだった回数
+}
+
+// ソートが失敗した理由を表す列挙型
+// 呼び出し側はバリアントでマッチすることで失敗の種類を区別できる
+#[derive(Debug, PartialEq)]
+pub enum SortError {
+    // 要素数が2のべき乗になっていない
+    NotPowerOfTwo { len: usize },
+    // cancelフラグによってソートが中断された
+    Cancelled,
+    // 要素数が多すぎて、再帰が深くなりすぎるおそれがある
+    TooLarge { len: usize },
+    // 一緒に並べ替える2つのスライスの要素数が異なる
+    LengthMismatch { keys: usize, values: usize },
+    // strideが0か、offsetがstride以上か、要素数がstrideで割り切れない
+    InvalidStride { len: usize, stride: usize, offset: usize },
+    // 有効な要素数valid_lenがスライスの要素数を超えている
+    ValidLenOutOfRange { len: usize, valid_len: usize },
+    // ソート中にcomparatorがパニックした
+    ComparatorPanicked,
+    // ソートする範囲start..endが、要素数lenのスライスに収まっていない
+    InvalidRange { len: usize, start: usize, end: usize },
+    // 要素が1つ以上必要な処理（中央値を求めるなど）に、空のスライスが渡された
+    Empty,
+}
+
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SortError::NotPowerOfTwo { len } => write!(
+                f,
+                "The length of x is not a power of two. (x.len(): {})",
+                len
+            ),
+            SortError::Cancelled => write!(f, "The sort was cancelled."),
+            SortError::TooLarge { len } => {
+                write!(f, "The length of x is too large to sort. (x.len(): {})", len)
+            }
+            SortError::LengthMismatch { keys, values } => write!(
+                f,
+                "The lengths of keys and values differ. (keys.len(): {}, values.len(): {})",
+                keys, values
+            ),
+            SortError::InvalidStride { len, stride, offset } => write!(
+                f,
+                "The stride or offset is invalid. (x.len(): {}, stride: {}, offset: {})",
+                len, stride, offset
+            ),
+            SortError::ValidLenOutOfRange { len, valid_len } => write!(
+                f,
+                "valid_len is greater than the length of x. (x.len(): {}, valid_len: {})",
+                len, valid_len
+            ),
+            SortError::ComparatorPanicked => write!(f, "The comparator panicked."),
+            SortError::InvalidRange { len, start, end } => write!(
+                f,
+                "The range is out of bounds. (x.len(): {}, range: {}..{})",
+                len, start, end
+            ),
+            SortError::Empty => write!(f, "x is empty."),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for SortError {}
+
+// fourth::try_sort_byが失敗した理由
+// Eはcomparatorが返すエラーの型
+#[derive(Debug, PartialEq)]
+pub enum TrySortError<E> {
+    // ソート自体が失敗した
+    Sort(SortError),
+    // comparatorがエラーを返した。最初に返されたエラーを持つ
+    Comparator(E),
+}
+
+impl<E> From<SortError> for TrySortError<E> {
+    fn from(e: SortError) -> Self {
+        TrySortError::Sort(e)
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for TrySortError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySortError::Sort(e) => e.fmt(f),
+            TrySortError::Comparator(e) => write!(f, "The comparator failed: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> Error for TrySortError<E> {}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseSortOrderError, SortError, SortOrder};
+
+    #[test]
+    fn display_not_power_of_two() {
+        let e = SortError::NotPowerOfTwo { len: 3 };
+        assert_eq!(
+            e.to_string(),
+            "The length of x is not a power of two. (x.len(): 3)"
+        );
+    }
+
+    #[test]
+    fn sort_order_is_copy() {
+        // Copyなので、変数に束縛した後も元の値を使える
+        let order = SortOrder::Descending;
+        let copied = order;
+        assert_eq!(order, copied);
+        assert_ne!(order, SortOrder::Ascending);
+        assert_eq!(format!("{:?}", order), "Descending");
+    }
+
+    #[test]
+    fn sort_order_round_trip() {
+        for &order in &[SortOrder::Ascending, SortOrder::Descending] {
+            assert_eq!(order.to_string().parse(), Ok(order));
+        }
+        assert_eq!("asc".parse(), Ok(SortOrder::Ascending));
+        assert_eq!("DESC".parse(), Ok(SortOrder::Descending));
+        assert_eq!("Ascending".parse(), Ok(SortOrder::Ascending));
+    }
+
+    #[test]
+    fn sort_order_rejects_unknown() {
+        let e = "up".parse::<SortOrder>().unwrap_err();
+        assert_eq!(e, ParseSortOrderError { input: "up".to_string() });
+        assert_eq!(
+            e.to_string(),
+            "Invalid sort order: \"up\". (expected asc, ascending, desc or descending)"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use super::utils::{next_power_of_two_len, power_of_two_exponent};
+use super::{Sentinel, SortError, SortOrder, SortStats};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+// Batcherの奇偶マージソート
+// バイトニックソートと同じく要素数が2のべき乗のソーティングネットワークだが、
+// 比較の回数はバイトニックソートより少ない（例：16要素なら80回ではなく63回）
+
+fn do_sort<T, F>(x: &mut [T], comparator: &F, stats: &mut SortStats)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    if x.len() > 1 {
+        let mid_point = x.len() / 2;
+        // 前半と後半をそれぞれ昇順にソートしてから、奇偶マージで1つにまとめる
+        do_sort(&mut x[..mid_point], comparator, stats);
+        do_sort(&mut x[mid_point..], comparator, stats);
+        merge(x, 0, 1, comparator, stats);
+    }
+}
+
+// xのうちlo, lo + r, lo + 2r, …の位置にある要素の列をマージする
+// この列の前半と後半はそれぞれソート済みでなければならない
+fn merge<T, F>(x: &mut [T], lo: usize, r: usize, comparator: &F, stats: &mut SortStats)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let step = r * 2;
+    if step < x.len() {
+        // 偶数番目の要素の列と奇数番目の要素の列をそれぞれマージする
+        merge(x, lo, step, comparator, stats);
+        merge(x, lo + r, step, comparator, stats);
+        // 最後に隣り合う奇数番目と偶数番目の要素を比べる
+        let mut i = lo + r;
+        while i + r < x.len() {
+            compare_and_swap(x, i, i + r, comparator, stats);
+            i += step;
+        }
+    } else {
+        compare_and_swap(x, lo, lo + r, comparator, stats);
+    }
+}
+
+fn compare_and_swap<T, F>(x: &mut [T], i: usize, j: usize, comparator: &F, stats: &mut SortStats)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    stats.comparisons += 1;
+    match comparator(&x[i], &x[j]) {
+        Ordering::Greater => {
+            x.swap(i, j);
+            stats.swaps += 1;
+        }
+        Ordering::Equal => stats.equals += 1,
+        Ordering::Less => (),
+    }
+}
+
+pub fn sort<T: Ord>(x: &mut [T], order: SortOrder) -> Result<(), SortError> {
+    match order {
+        SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+// 要素数が2のべき乗でないベクタを、番兵で実際に埋めてからソートする
+// 番兵は末尾に並ぶので、ソート後に取り除けば元の要素だけがソートされて残る
+// Sentinelを実装していない型は、要素数が2のべき乗のスライスをsortでソートする
+pub fn sort_padded<T: Ord + Sentinel>(x: &mut Vec<T>, order: SortOrder) -> Result<(), SortError> {
+    let len = x.len();
+    let sentinel = match order {
+        SortOrder::Ascending => T::max_value,
+        SortOrder::Descending => T::min_value,
+    };
+    x.resize_with(next_power_of_two_len(len), sentinel);
+    let result = sort(x, order);
+    x.truncate(len);
+    result
+}
+
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    sort_by_counted(x, comparator).map(|_| ())
+}
+
+// ソートしながら比較と交換の回数を数え、SortStatsとして返す
+pub fn sort_by_counted<T, F>(x: &mut [T], comparator: &F) -> Result<SortStats, SortError>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    // 空のスライスと1要素のスライスは常にソート済みなので、比較せずに成功とする
+    if x.len() <= 1 {
+        return Ok(SortStats::default());
+    }
+    if power_of_two_exponent(x.len()).is_some() {
+        let mut stats = SortStats::default();
+        do_sort(x, comparator, &mut stats);
+        Ok(stats)
+    } else {
+        Err(SortError::NotPowerOfTwo { len: x.len() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sort, sort_by, sort_by_counted, sort_padded};
+    use crate::fourth;
+    use crate::SortError::*;
+    use crate::SortOrder::*;
+    use crate::utils::{is_sorted_ascending, is_sorted_descending, new_u32_vec};
+
+    #[test]
+    fn sort_u32_ascending() {
+        let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
+    }
+
+    #[test]
+    fn sort_u32_descending() {
+        let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
+    }
+
+    #[test]
+    fn sort_to_fail() {
+        let mut x = vec![10, 30, 11]; // x.len()が2のべき乗になっていない
+        assert_eq!(sort(&mut x, Ascending), Err(NotPowerOfTwo { len: 3 }));
+    }
+
+    #[test]
+    fn sort_empty_and_single() {
+        // 空のスライスと1要素のスライスは、2のべき乗でなくてもエラーにならない
+        let mut x: Vec<u32> = vec![];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![]);
+
+        let mut x = vec![42];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![42]);
+    }
+
+    #[test]
+    fn sort_padded_u8() {
+        // 要素数5のベクタは8要素まで番兵で埋めてソートされる
+        // 番兵と同じ値（255と0）が含まれていても、結果の要素数は変わらない
+        let mut x: Vec<u8> = vec![30, 255, 4, 0, 21];
+        assert_eq!(sort_padded(&mut x, Ascending), Ok(()));
+        assert_eq!(x, vec![0, 4, 21, 30, 255]);
+
+        assert_eq!(sort_padded(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![255, 30, 21, 4, 0]);
+    }
+
+    #[test]
+    fn sort_u32_large() {
+        let mut x = new_u32_vec(65536);
+        assert_eq!(sort_by(&mut x, &|a, b| a.cmp(b)), Ok(()));
+        assert!(is_sorted_ascending(&x));
+
+        let mut x = new_u32_vec(65536);
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert!(is_sorted_descending(&x));
+    }
+
+    #[test]
+    fn fewer_comparisons_than_bitonic() {
+        // 16要素ならバイトニックソートは80回、奇偶マージソートは63回比較する
+        let mut x = new_u32_vec(16);
+        let mut y = x.clone();
+        let oddeven = sort_by_counted(&mut x, &|a, b| a.cmp(b)).unwrap();
+        let bitonic = fourth::sort_by_counted(&mut y, &|a, b| a.cmp(b)).unwrap();
+        assert_eq!(x, y);
+        assert_eq!(oddeven.comparisons, 63);
+        assert_eq!(bitonic.comparisons, 80);
+    }
+}
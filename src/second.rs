@@ -1,5 +1,6 @@
 
-use super::SortOrder;
+use super::utils::power_of_two_exponent;
+use super::{SortError, SortOrder};
 
 fn do_sort<T: Ord>(x: &mut [T], up: bool) {
     if x.len() > 1 {
@@ -10,15 +11,20 @@ fn do_sort<T: Ord>(x: &mut [T], up: bool) {
     }
 }
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
-    if x.len().is_power_of_two() {
-        match *order {
+pub fn sort<T: Ord>(x: &mut [T], order: SortOrder) -> Result<(), SortError> {
+    // 空のスライスと1要素のスライスは常にソート済みなので、何もせずに成功とする
+    // （0は2のべき乗ではないので、要素数を確認する前に戻る必要がある）
+    if x.len() <= 1 {
+        return Ok(());
+    }
+    if power_of_two_exponent(x.len()).is_some() {
+        match order {
             SortOrder::Ascending => do_sort(x, true),
             SortOrder::Descending => do_sort(x, false),
         }
         Ok(())
     } else {
-        Err(format!("The length of x is not a power of two. (x.len(): {})", x.len()))
+        Err(SortError::NotPowerOfTwo { len: x.len() })
     }
 }
 
@@ -59,7 +65,7 @@ mod tests {
 
         // xのスライスを作成し、sort関数を呼び出す
         // &mut x は&mut x[..]と書いてもいい
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
 
         // xの要素が昇順にソートされていることを確認する
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
@@ -68,7 +74,7 @@ mod tests {
     #[test]
     fn sort_u32_descending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
         // xの要素が降順にソートされていることを確認する
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
@@ -77,21 +83,35 @@ mod tests {
     fn sort_str_ascending() {
         // 文字列のベクタを作りソートする
         let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
         assert_eq!(x, vec!["GC", "Rust", "and", "fast", "is", "memory-efficient", "no", "with"]);
     }
 
     #[test]
     fn sort_str_descending() {
         let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
         assert_eq!(x, vec!["with", "no", "memory-efficient", "is", "fast", "and", "Rust", "GC"]);
     }
 
     #[test]
     fn sort_to_fail() {
         let mut x = vec![10, 30, 11]; // x.len()が2のべき乗になっていない
-        assert!(sort(&mut x, &Ascending).is_err()); // 戻り値はErr
+        assert!(sort(&mut x, Ascending).is_err()); // 戻り値はErr
+    }
+
+    #[test]
+    fn sort_empty_and_single() {
+        // 空のスライスと1要素のスライスは、2のべき乗でなくてもエラーにならない
+        let mut x: Vec<u32> = vec![];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![]);
+
+        let mut x = vec![42];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![42]);
     }
 }
 
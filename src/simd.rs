@@ -0,0 +1,185 @@
+use super::fourth::check_depth;
+use super::{SortError, SortOrder};
+
+// u32とi32のスライスを自然な順序でソートする、SIMD命令を使ったバイトニックソート
+// simdフィーチャーを有効にすると使える
+//
+// バイトニックソートの比較と交換は、前半と後半の同じ位置にある要素の最小値と最大値を
+// 求めることと同じなので、8要素ずつまとめてAVX2の命令で処理できる
+// AVX2が使えないCPUや8要素に満たない端数の要素は、通常のループで処理する
+
+// fourth::sortと同じく、要素数が多すぎて再帰が深くなりすぎる場合はTooLargeを返す
+pub fn sort_u32(x: &mut [u32], order: SortOrder) -> Result<(), SortError> {
+    check_depth(x.len())?;
+    do_sort(x, is_forward(order), avx2_available());
+    Ok(())
+}
+
+pub fn sort_i32(x: &mut [i32], order: SortOrder) -> Result<(), SortError> {
+    check_depth(x.len())?;
+    do_sort(x, is_forward(order), avx2_available());
+    Ok(())
+}
+
+fn is_forward(order: SortOrder) -> bool {
+    match order {
+        SortOrder::Ascending => true,
+        SortOrder::Descending => false,
+    }
+}
+
+// 実行しているCPUがAVX2命令に対応しているかを調べる
+#[cfg(target_arch = "x86_64")]
+fn avx2_available() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn avx2_available() -> bool {
+    false
+}
+
+// 再帰の構造はfourthのdo_sortと同じ。要素数が2のべき乗でなくてもソートできる
+fn do_sort<T: Lane>(x: &mut [T], forward: bool, avx2: bool) {
+    if x.len() > 1 {
+        let mid_point = x.len().next_power_of_two() / 2;
+        let (first, second) = x.split_at_mut(mid_point);
+        do_sort(first, !forward, avx2);
+        do_sort(second, forward, avx2);
+        sub_sort(x, forward, avx2);
+    }
+}
+
+fn sub_sort<T: Lane>(x: &mut [T], forward: bool, avx2: bool) {
+    if x.len() > 1 {
+        let mid_point = x.len().next_power_of_two() / 2;
+        let (first, second) = x.split_at_mut(mid_point);
+        // 相手が番兵になる要素は比較しないので、後半と同じ要素数だけ比べる
+        let len = second.len();
+        T::compare_and_swap(&mut first[..len], second, forward, avx2);
+        sub_sort(first, forward, avx2);
+        sub_sort(second, forward, avx2);
+    }
+}
+
+// SIMD命令で比較と交換ができる整数型
+trait Lane: Copy + Ord {
+    // aとbの同じ位置の要素を比べ、forwardならaに小さい方を、bに大きい方を置く
+    // forwardでなければ逆に置く。aとbの要素数は等しくなければならない
+    fn compare_and_swap(a: &mut [Self], b: &mut [Self], forward: bool, avx2: bool);
+}
+
+macro_rules! impl_lane {
+    ($t:ty, $avx2_fn:ident) => {
+        impl Lane for $t {
+            fn compare_and_swap(a: &mut [Self], b: &mut [Self], forward: bool, avx2: bool) {
+                // AVX2で処理できた要素数。残りは通常のループで処理する
+                #[allow(unused_mut)]
+                let mut done = 0;
+                #[cfg(target_arch = "x86_64")]
+                {
+                    if avx2 {
+                        // 安全性：avx2がtrueなのはCPUがAVX2に対応していると確認できた時だけ
+                        done = unsafe { x86::$avx2_fn(a, b, forward) };
+                    }
+                }
+                #[cfg(not(target_arch = "x86_64"))]
+                let _ = avx2;
+                scalar_compare_and_swap(&mut a[done..], &mut b[done..], forward);
+            }
+        }
+    };
+}
+
+impl_lane!(u32, compare_and_swap_u32);
+impl_lane!(i32, compare_and_swap_i32);
+
+fn scalar_compare_and_swap<T: Ord>(a: &mut [T], b: &mut [T], forward: bool) {
+    for (p, q) in a.iter_mut().zip(b.iter_mut()) {
+        let swap = if forward { *p > *q } else { *p < *q };
+        if swap {
+            std::mem::swap(p, q);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    macro_rules! avx2_compare_and_swap {
+        ($name:ident, $t:ty, $min:ident, $max:ident) => {
+            // aとbの先頭から8要素ずつAVX2で比較と交換を行い、処理した要素数を返す
+            // 安全性：AVX2に対応したCPUで呼び出さなければならない
+            #[target_feature(enable = "avx2")]
+            pub unsafe fn $name(a: &mut [$t], b: &mut [$t], forward: bool) -> usize {
+                let len = a.len().min(b.len());
+                let chunks = len / 8;
+                for c in 0..chunks {
+                    // 境界が揃っているとは限らないので、loadu/storeuを使う
+                    let pa = a.as_mut_ptr().add(c * 8) as *mut __m256i;
+                    let pb = b.as_mut_ptr().add(c * 8) as *mut __m256i;
+                    let va = _mm256_loadu_si256(pa);
+                    let vb = _mm256_loadu_si256(pb);
+                    let lo = $min(va, vb);
+                    let hi = $max(va, vb);
+                    if forward {
+                        _mm256_storeu_si256(pa, lo);
+                        _mm256_storeu_si256(pb, hi);
+                    } else {
+                        _mm256_storeu_si256(pa, hi);
+                        _mm256_storeu_si256(pb, lo);
+                    }
+                }
+                chunks * 8
+            }
+        };
+    }
+
+    avx2_compare_and_swap!(compare_and_swap_u32, u32, _mm256_min_epu32, _mm256_max_epu32);
+    avx2_compare_and_swap!(compare_and_swap_i32, i32, _mm256_min_epi32, _mm256_max_epi32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{do_sort, sort_i32, sort_u32};
+    use crate::SortOrder::*;
+    use crate::utils::{new_u32_vec, new_vec};
+
+    #[test]
+    fn sort_u32_matches_std() {
+        for &len in &[0, 1, 7, 8, 9, 100, 1000, 1024, 65536] {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            assert_eq!(sort_u32(&mut x, Ascending), Ok(()));
+            assert_eq!(x, expected, "len: {}", len);
+
+            expected.reverse();
+            assert_eq!(sort_u32(&mut x, Descending), Ok(()));
+            assert_eq!(x, expected, "len: {}", len);
+        }
+    }
+
+    #[test]
+    fn sort_i32_matches_std() {
+        for &len in &[5, 16, 1000, 4096] {
+            // 負の値を含むので、符号付きの比較が使われていることを確認できる
+            let mut x: Vec<i32> = new_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            assert_eq!(sort_i32(&mut x, Ascending), Ok(()));
+            assert_eq!(x, expected, "len: {}", len);
+        }
+    }
+
+    #[test]
+    fn scalar_fallback_matches_std() {
+        // AVX2を使わない経路でも同じ結果になる
+        let mut x = new_u32_vec(1000);
+        let mut expected = x.clone();
+        expected.sort();
+        do_sort(&mut x, true, false);
+        assert_eq!(x, expected);
+    }
+}
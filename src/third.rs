@@ -1,5 +1,6 @@
-use super::SortOrder;
-use std::cmp::Ordering;
+use super::utils::power_of_two_exponent;
+use super::{SortError, SortOrder};
+use core::cmp::Ordering;
 
 fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
 where
@@ -16,10 +17,10 @@ where
     }
 }
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort<T: Ord>(x: &mut [T], order: SortOrder) -> Result<(), SortError> {
     // do_sortを呼ぶ代わりにsort_byを呼ぶ
     // is_power_of_twoはsort_byが呼ぶのでここからは削除
-    match *order {
+    match order {
         SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
         SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
     }
@@ -57,18 +58,20 @@ where
     }
 }
 
-pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), String>
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
 where
     F: Fn(&T, &T) -> Ordering,
 {
-    if x.len().is_power_of_two() {
+    // 空のスライスと1要素のスライスは常にソート済みなので、何もせずに成功とする
+    // （0は2のべき乗ではないので、要素数を確認する前に戻る必要がある）
+    if x.len() <= 1 {
+        return Ok(());
+    }
+    if power_of_two_exponent(x.len()).is_some() {
         do_sort(x, true, comparator);
         Ok(())
     } else {
-        Err(format!(
-            "The length of x is not a power of two. (x.len(): {})",
-            x.len()
-        ))
+        Err(SortError::NotPowerOfTwo { len: x.len() })
     }
 }
 
@@ -77,6 +80,7 @@ mod tests {
     // 親モジュール(first)のsort関数を使用する
     use super::{sort, sort_by};
     use crate::SortOrder::*;
+    use crate::SortError::*;
     use crate::utils::{new_u32_vec, is_sorted_ascending, is_sorted_descending};
 
     // #[test]のついた関数はcargo testとした時に実行される
@@ -88,7 +92,7 @@ mod tests {
 
         // xのスライスを作成し、sort関数を呼び出す
         // &mut x は&mut x[..]と書いてもいい
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
 
         // xの要素が昇順にソートされていることを確認する
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
@@ -97,7 +101,7 @@ mod tests {
     #[test]
     fn sort_u32_descending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
         // xの要素が降順にソートされていることを確認する
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
@@ -115,7 +119,7 @@ mod tests {
             "no",
             "GC",
         ];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
         assert_eq!(
             x,
             vec![
@@ -143,7 +147,7 @@ mod tests {
             "no",
             "GC",
         ];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
         assert_eq!(
             x,
             vec![
@@ -162,7 +166,23 @@ mod tests {
     #[test]
     fn sort_to_fail() {
         let mut x = vec![10, 30, 11]; // x.len()が2のべき乗になっていない
-        assert!(sort(&mut x, &Ascending).is_err()); // 戻り値はErr
+        assert!(sort(&mut x, Ascending).is_err()); // 戻り値はErr
+        // エラーの種類はバリアントで判別できる
+        assert_eq!(sort(&mut x, Ascending), Err(NotPowerOfTwo { len: 3 }));
+    }
+
+    #[test]
+    fn sort_empty_and_single() {
+        // 空のスライスと1要素のスライスは、2のべき乗でなくてもエラーにならない
+        let mut x: Vec<u32> = vec![];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![]);
+
+        let mut x = vec![42];
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert_eq!(sort(&mut x, Descending), Ok(()));
+        assert_eq!(x, vec![42]);
     }
 
     // 構造体Studentを定義する
@@ -255,12 +275,12 @@ mod tests {
             // 乱数で65,536要素のデータ列を作る（65,536は2の16乗）
             let mut x = new_u32_vec(65536);
             // 昇順にソートする
-            assert_eq!(sort(&mut x, &Ascending), Ok(()));
+            assert_eq!(sort(&mut x, Ascending), Ok(()));
             assert!(is_sorted_ascending(&x));
         }
         {
             let mut x = new_u32_vec(65536);
-            assert_eq!(sort(&mut x, &Descending), Ok(()));
+            assert_eq!(sort(&mut x, Descending), Ok(()));
             assert!(is_sorted_descending(&x));
         }
     }
@@ -1,10 +1,47 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+#[cfg(any(feature = "std", test))]
+use core::ops::Range;
+#[cfg(any(feature = "std", test))]
 use rand::{Rng, SeedableRng};
-use rand::distributions::Standard;
+#[cfg(any(feature = "std", test))]
+use rand::distributions::{Distribution, Standard};
+#[cfg(any(feature = "std", test))]
 use rand_pcg::Pcg64Mcg;
 
+// 乱数でテストデータを作る関数はrandクレートを使うので、stdフィーチャーが必要
+#[cfg(any(feature = "std", test))]
 pub fn new_u32_vec(n: usize) -> Vec<u32> {
-    // RNGを初期化する。再現性をもたせるため毎回同じシードを使う
-    let mut rng = Pcg64Mcg::from_seed([0; 16]);
+    new_vec(n)
+}
+
+// シードを指定してn要素のu32型ベクタを作る
+// シードを変えることで、毎回異なるテストデータを得られる
+#[cfg(any(feature = "std", test))]
+pub fn new_u32_vec_seeded(n: usize, seed: [u8; 16]) -> Vec<u32> {
+    new_vec_seeded(n, seed)
+}
+
+// 乱数でn要素のベクタを作る。要素の型はStandard分布から生成できる型なら何でもよい
+// 例：new_vec::<i64>(1024)、new_vec::<f64>(1024)
+#[cfg(any(feature = "std", test))]
+pub fn new_vec<T>(n: usize) -> Vec<T>
+where
+    Standard: Distribution<T>,
+{
+    // 再現性をもたせるため毎回同じシードを使う
+    new_vec_seeded(n, [0; 16])
+}
+
+// シードを指定して乱数でn要素のベクタを作る
+#[cfg(any(feature = "std", test))]
+pub fn new_vec_seeded<T>(n: usize, seed: [u8; 16]) -> Vec<T>
+where
+    Standard: Distribution<T>,
+{
+    // RNGを初期化する。同じシードからは同じデータ列が作られる
+    let mut rng = Pcg64Mcg::from_seed(seed);
 ////    // n個の要素が格納できるようベクタを初期化する
 ////    let mut v = Vec::with_capacity(n);
 //
@@ -21,17 +58,438 @@ pub fn new_u32_vec(n: usize) -> Vec<u32> {
     rng.sample_iter(&Standard).take(n).collect()
 }
 
+// シードを指定して、rangeの範囲（終端は含まない）の値だけからなるn要素のu32型ベクタを作る
+// 範囲を狭くすれば、同じ値の要素を多く含むテストデータを作れる
+// nが0なら空のベクタを返す。nが1以上でrangeが空（startがend以上）ならパニックする
+#[cfg(any(feature = "std", test))]
+pub fn new_u32_vec_in_range(n: usize, range: Range<u32>, seed: [u8; 16]) -> Vec<u32> {
+    assert!(
+        n == 0 || range.start < range.end,
+        "range is empty: {}..{}",
+        range.start,
+        range.end
+    );
+    let mut rng = Pcg64Mcg::from_seed(seed);
+    (0..n).map(|_| rng.gen_range(range.start, range.end)).collect()
+}
+
+// 0からn - 1までの昇順のベクタを作り、ランダムに選んだ2要素の交換をdisorder * n回行う
+// disorderが0.0ならソート済みのまま、大きくするほど乱れたデータになる
+// ソート済みに近いデータでfourth::sort_by_adaptiveなどの速さを測るのに使う
+// 要素はu32なので、nがu32::MAXより大きい場合はパニックする
+#[cfg(any(feature = "std", test))]
+pub fn new_partially_sorted(n: usize, disorder: f64, seed: [u8; 16]) -> Vec<u32> {
+    use core::convert::TryFrom;
+
+    let len = u32::try_from(n).expect("n must not be greater than u32::MAX");
+    let mut x: Vec<u32> = (0..len).collect();
+    if n < 2 {
+        return x;
+    }
+    let mut rng = Pcg64Mcg::from_seed(seed);
+    let swaps = (disorder * n as f64) as usize;
+    for _ in 0..swaps {
+        let i = rng.gen_range(0, n);
+        let j = rng.gen_range(0, n);
+        x.swap(i, j);
+    }
+    x
+}
+
 pub fn is_sorted_ascending<T: Ord>(x: &[T]) -> bool {
+    first_unsorted_ascending(x).is_none()
+}
+
+pub fn is_sorted_descending<T: Ord>(x: &[T]) -> bool {
+    first_unsorted_descending(x).is_none()
+}
+
+// 昇順になっていない最初の位置iを返す（x[i] > x[i + 1]となるi）
+// ソート済みならNoneを返す。テストが失敗した時にどこが崩れているかを調べるのに使う
+pub fn first_unsorted_ascending<T: Ord>(x: &[T]) -> Option<usize> {
     // windows(2)は元のイテレータから1要素刻みで2要素ずつ値を取り出す新しいイテレータを返す
     // 例えば元が[1,2,3,4]なら[1,2][2,3][3,4]を順に返す
     //
-    // all(...)はイテレータから値（例:[1,2]）を取り出し、クロージャに渡す
-    // クロージャがfalseを返したらそこで処理を打ち切りfalseを返す
-    // クロージャがtrueを返している間は、イテレータから次の値を取り出し、クロージャへ与え続ける
-    // クロージャが一度もfalseを返さなかったらall(...)はtrueを返す
-    x.windows(2).all(|pair| pair[0] <= pair[1])
+    // position(...)はイテレータから値（例:[1,2]）を取り出し、クロージャに渡す
+    // クロージャがtrueを返したらそこで処理を打ち切り、その値の位置をSomeで返す
+    // クロージャが一度もtrueを返さなかったらposition(...)はNoneを返す
+    x.windows(2).position(|pair| pair[0] > pair[1])
 }
 
-pub fn is_sorted_descending<T: Ord>(x: &[T]) -> bool {
-    x.windows(2).all(|pair| pair[0] >= pair[1])
+// 降順になっていない最初の位置iを返す（x[i] < x[i + 1]となるi）
+pub fn first_unsorted_descending<T: Ord>(x: &[T]) -> Option<usize> {
+    x.windows(2).position(|pair| pair[0] < pair[1])
+}
+
+// comparatorで示される順序でxがソートされているかを確認する
+// 隣り合う2要素が逆順（Greater）になっていなければtrueを返す
+pub fn is_sorted_by<T, F>(x: &[T], comparator: &F) -> bool
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    x.windows(2).all(|pair| comparator(&pair[0], &pair[1]) != Ordering::Greater)
+}
+
+// comparatorで示される順序でxが昇順になっているかを確認する。is_sorted_byと同じ
+// Ordを実装していない型（f64など）でも、比較関数を渡せば確認できる
+pub fn is_sorted_ascending_by<T, F>(x: &[T], comparator: &F) -> bool
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    is_sorted_by(x, comparator)
+}
+
+// f64のスライスが、f64::total_cmpの全順序で昇順になっているかを確認する
+// fourth::sort_by_float_totalで昇順にソートした結果（NaNは末尾）を確かめるのに使う
+pub fn is_sorted_ascending_f64(x: &[f64]) -> bool {
+    is_sorted_ascending_by(x, &|a: &f64, b: &f64| a.total_cmp(b))
+}
+
+// permの順にxの要素を複製して並べた、新しいベクタを返す（結果のi番目はx[perm[i]]）
+// fourth::argsortが返したインデックスの列を渡せば、xをソートした複製が得られる
+// permが0..x.len()の並べ替えになっていなければパニックする
+pub fn apply_permutation<T: Clone>(x: &[T], perm: &[usize]) -> Vec<T> {
+    check_permutation(x.len(), perm);
+    perm.iter().map(|&i| x[i].clone()).collect()
+}
+
+// apply_permutationと同じ並べ替えを、要素を複製せずにその場で行う
+// 巡回置換を一つずつたどりながら要素を交換するので、処理後のpermの内容は規定しない
+// permが0..x.len()の並べ替えになっていなければパニックする
+pub fn apply_permutation_in_place<T>(x: &mut [T], perm: &mut [usize]) {
+    check_permutation(x.len(), perm);
+    permute_with(perm, |i, j| x.swap(i, j));
+}
+
+// permが0..lenの各値をちょうど1回ずつ含んでいるかを確認し、そうでなければパニックする
+fn check_permutation(len: usize, perm: &[usize]) {
+    assert_eq!(perm.len(), len, "perm and x have different lengths");
+    let mut seen = vec![false; len];
+    for &i in perm {
+        assert!(i < len && !seen[i], "perm is not a permutation of 0..{}", len);
+        seen[i] = true;
+    }
+}
+
+// permで表される並べ替えを、i番目とj番目を交換するswapの呼び出しの列として行う
+// fourthのソートで、キーと値のように複数のスライスを同じ順に並べ替える時にも使う
+pub(crate) fn permute_with<S: FnMut(usize, usize)>(perm: &mut [usize], mut swap: S) {
+    for start in 0..perm.len() {
+        let mut current = start;
+        // 並べ替えが済んだ位置はperm[i] == iになっている
+        while perm[current] != start {
+            let next = perm[current];
+            swap(current, next);
+            perm[current] = current;
+            current = next;
+        }
+        perm[current] = current;
+    }
+}
+
+// aとbが同じ要素を同じ個数ずつ含んでいる（一方が他方の並べ替えになっている）ならtrueを返す
+// ソートの前後で比べれば、要素が失われたり重複したりしていないことを確かめられる
+// is_sorted_ascendingなどと合わせて使えば、ソートの結果が正しいことを確認できる
+// 両方を複製してソートしてから比べるので、O(n log n)の時間と2n個分のメモリを使う
+pub fn is_permutation_of<T: Ord + Clone>(a: &[T], b: &[T]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort_unstable();
+    b.sort_unstable();
+    a == b
+}
+
+// xの要素の並びをその場で逆順にする
+// 昇順にソートしたスライスに使えば、降順にソートしたのと同じ結果になる
+pub fn reverse_in_place<T>(x: &mut [T]) {
+    x.reverse();
+}
+
+// xの転倒数（i < jかつx[i] > x[j]となる組(i, j)の数）を返す
+// ソート済みなら0、逆順なら最大のn * (n - 1) / 2になるので、どれだけ整列しているかの目安になる
+// バイトニックソートの交換回数は転倒数と一致しないので、xの複製をマージソートしながら数える
+pub fn count_inversions<T: Ord + Clone>(x: &[T]) -> u64 {
+    let mut v = x.to_vec();
+    let mut buf = Vec::with_capacity(v.len());
+    merge_count(&mut v, &mut buf)
+}
+
+// xをマージソートし、その間に見つかった転倒の数を返す。bufはマージに使う作業用のベクタ
+fn merge_count<T: Ord + Clone>(x: &mut [T], buf: &mut Vec<T>) -> u64 {
+    if x.len() <= 1 {
+        return 0;
+    }
+    let mid_point = x.len() / 2;
+    let mut count = merge_count(&mut x[..mid_point], buf) + merge_count(&mut x[mid_point..], buf);
+
+    buf.clear();
+    let (first, second) = x.split_at(mid_point);
+    let (mut i, mut j) = (0, 0);
+    while i < first.len() && j < second.len() {
+        if second[j] < first[i] {
+            // second[j]は、firstに残っている要素すべてより小さい
+            count += (first.len() - i) as u64;
+            buf.push(second[j].clone());
+            j += 1;
+        } else {
+            buf.push(first[i].clone());
+            i += 1;
+        }
+    }
+    buf.extend_from_slice(&first[i..]);
+    buf.extend_from_slice(&second[j..]);
+    x.clone_from_slice(buf);
+    count
+}
+
+// xがバイトニック列かを確認する。バイトニック列とは、単調に増加してから単調に減少する列
+// （またはそれを巡回させた列）のこと。単調増加や単調減少の列もバイトニック列に含まれる
+// fourth::bitonic_mergeに渡す前に、入力が正しいかを調べるのに使う
+pub fn is_bitonic<T: Ord>(x: &[T]) -> bool {
+    is_bitonic_by(x, &|a: &T, b: &T| a.cmp(b))
+}
+
+// comparatorで示される順序で、xがバイトニック列かを確認する
+pub fn is_bitonic_by<T, F>(x: &[T], comparator: &F) -> bool
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    // 末尾から先頭に戻る組も含めて、隣り合う要素の大小の向き（Equalは除く）が
+    // 何回変わるかを数える。バイトニック列なら増加から減少、減少から増加への2回以下になる
+    let mut changes = 0;
+    let mut prev: Option<Ordering> = None;
+    let mut first: Option<Ordering> = None;
+    for i in 0..x.len() {
+        let ordering = comparator(&x[i], &x[(i + 1) % x.len()]);
+        if ordering == Ordering::Equal {
+            continue;
+        }
+        match prev {
+            Some(p) if p != ordering => changes += 1,
+            None => first = Some(ordering),
+            _ => (),
+        }
+        prev = Some(ordering);
+    }
+    // 最後の向きから最初の向きへの変化も数える
+    if prev.is_some() && prev != first {
+        changes += 1;
+    }
+    changes <= 2
+}
+
+// nが2のべき乗（2^k）ならSome(k)を、そうでなければNoneを返す。0は2のべき乗ではない
+// 要素数が2のべき乗かを確認し、同時に段数などの計算に使う指数も得たい時に使う
+pub fn power_of_two_exponent(n: usize) -> Option<u32> {
+    if n.is_power_of_two() {
+        Some(n.trailing_zeros())
+    } else {
+        None
+    }
+}
+
+// 要素数nを2のべき乗に切り上げた長さ（n以上で最小の2のべき乗）を返す。nが0なら1を返す
+// 要素数が2のべき乗でないスライスは、この長さまで番兵でパディングしたものとしてソートされる
+// ソート前にバッファの大きさを決めたい時などに使う
+pub fn next_power_of_two_len(n: usize) -> usize {
+    n.next_power_of_two()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_permutation, apply_permutation_in_place, count_inversions, first_unsorted_ascending,
+        first_unsorted_descending, is_bitonic, is_bitonic_by, is_permutation_of,
+        is_sorted_ascending, is_sorted_ascending_by, is_sorted_ascending_f64, new_partially_sorted,
+        new_u32_vec, new_u32_vec_in_range, next_power_of_two_len, power_of_two_exponent,
+        reverse_in_place,
+    };
+
+    #[test]
+    fn permutation_of_sorted() {
+        use crate::SortOrder::Ascending;
+        use crate::fourth::sort;
+
+        let original = new_u32_vec(1000);
+        let mut x = original.clone();
+        assert_eq!(sort(&mut x, Ascending), Ok(()));
+        assert!(is_permutation_of(&x, &original));
+
+        // 要素が1つ失われた場合や、要素が別の要素の重複に置き換わった場合はfalse
+        assert!(!is_permutation_of(&x[1..], &original));
+        assert!(is_permutation_of(&[1, 2, 2], &[2, 1, 2]));
+        assert!(!is_permutation_of(&[1, 2, 2], &[1, 1, 2]));
+    }
+
+    #[test]
+    fn first_unsorted_position() {
+        assert_eq!(first_unsorted_ascending(&[1, 2, 2, 5, 3, 4]), Some(3));
+        assert_eq!(first_unsorted_ascending(&[1, 2, 2, 3]), None);
+        assert_eq!(first_unsorted_descending(&[5, 4, 6, 1]), Some(1));
+        assert_eq!(first_unsorted_descending(&[5, 4, 4, 1]), None);
+
+        // 空のスライスと1要素のスライスは常にソート済み
+        let empty: [u32; 0] = [];
+        assert_eq!(first_unsorted_ascending(&empty), None);
+        assert_eq!(first_unsorted_descending(&[1]), None);
+    }
+
+    #[test]
+    fn is_sorted_ascending_floats() {
+        assert!(is_sorted_ascending_f64(&[-1.5, -0.0, 0.0, 2.0, f64::INFINITY, f64::NAN]));
+        assert!(!is_sorted_ascending_f64(&[1.0, f64::NAN, 2.0]));
+        assert!(!is_sorted_ascending_f64(&[2.0, 1.0]));
+        // total_cmpでは-0.0は+0.0より小さい
+        assert!(!is_sorted_ascending_f64(&[0.0, -0.0]));
+        assert!(is_sorted_ascending_f64(&[]));
+
+        let x = [3.0, 2.0, 1.0];
+        assert!(is_sorted_ascending_by(&x, &|a: &f64, b: &f64| b.total_cmp(a)));
+    }
+
+    #[test]
+    fn apply_permutations() {
+        let x = vec!['a', 'b', 'c', 'd', 'e'];
+        let perm = vec![3, 0, 4, 1, 2];
+        let expected = vec!['d', 'a', 'e', 'b', 'c'];
+        assert_eq!(apply_permutation(&x, &perm), expected);
+
+        let mut y = x.clone();
+        let mut p = perm.clone();
+        apply_permutation_in_place(&mut y, &mut p);
+        assert_eq!(y, expected);
+
+        // 大きな入力でも、複製して並べた結果とその場で並べ替えた結果は一致する
+        let x = new_u32_vec(1000);
+        let mut perm: Vec<usize> = (0..x.len()).collect();
+        perm.sort_by_key(|&i| x[i]);
+        let copied = apply_permutation(&x, &perm);
+        let mut y = x.clone();
+        apply_permutation_in_place(&mut y, &mut perm);
+        assert_eq!(y, copied);
+    }
+
+    #[test]
+    #[should_panic(expected = "perm is not a permutation")]
+    fn apply_permutation_rejects_duplicates() {
+        apply_permutation(&[1, 2, 3], &[0, 2, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "different lengths")]
+    fn apply_permutation_in_place_rejects_short_perm() {
+        apply_permutation_in_place(&mut [1, 2, 3], &mut [1, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "range is empty: 5..5")]
+    fn vec_in_range_rejects_empty_range() {
+        new_u32_vec_in_range(3, 5..5, [0; 16]);
+    }
+
+    #[test]
+    fn vec_in_range_empty() {
+        assert_eq!(new_u32_vec_in_range(0, 5..5, [0; 16]), vec![]);
+        let x = new_u32_vec_in_range(100, 5..8, [0; 16]);
+        assert!(x.len() == 100 && x.iter().all(|v| (5..8).contains(v)));
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    #[should_panic(expected = "n must not be greater than u32::MAX")]
+    fn partially_sorted_rejects_too_many() {
+        // 要素を作る前にパニックするので、メモリは確保されない
+        new_partially_sorted(u32::MAX as usize + 1, 0.0, [0; 16]);
+    }
+
+    #[test]
+    fn partially_sorted_disorder() {
+        let x = new_partially_sorted(1000, 0.0, [0; 16]);
+        assert!(is_sorted_ascending(&x));
+        assert_eq!(x.len(), 1000);
+
+        // 交換するだけなので、0からn - 1までの値がちょうど1回ずつ含まれる
+        let mut y = new_partially_sorted(1000, 0.1, [1; 16]);
+        assert!(!is_sorted_ascending(&y));
+        // 乱れ具合が大きいほど転倒数も多くなる
+        let z = new_partially_sorted(1000, 1.0, [1; 16]);
+        assert!(count_inversions(&y) < count_inversions(&z));
+        y.sort();
+        assert_eq!(y, x);
+    }
+
+    #[test]
+    fn next_power_of_two_len_boundaries() {
+        assert_eq!(next_power_of_two_len(0), 1);
+        assert_eq!(next_power_of_two_len(1), 1);
+        assert_eq!(next_power_of_two_len(2), 2);
+        assert_eq!(next_power_of_two_len(3), 4);
+        assert_eq!(next_power_of_two_len(1024), 1024);
+        assert_eq!(next_power_of_two_len(1025), 2048);
+    }
+
+    #[test]
+    fn is_bitonic_sequences() {
+        assert!(is_bitonic(&[1, 3, 5, 4, 2]));
+        // 単調な列もバイトニック列
+        assert!(is_bitonic(&[1, 2, 3, 4]));
+        assert!(is_bitonic(&[4, 3, 2, 1]));
+        // 増加と減少を2回以上繰り返す列はバイトニック列ではない
+        assert!(!is_bitonic(&[1, 3, 2, 4]));
+
+        // comparatorの順序で確かめることもできる
+        assert!(is_bitonic_by(&[5, 3, 1, 2, 4], &|a: &i32, b: &i32| b.cmp(a)));
+        assert!(!is_bitonic_by(&[1, 3, 2, 4], &|a: &i32, b: &i32| b.cmp(a)));
+
+        // バイトニック列を巡回させた列や、等しい要素を含む列もバイトニック列
+        assert!(is_bitonic(&[4, 2, 1, 3, 5]));
+        assert!(is_bitonic(&[1, 1, 3, 3, 2, 2]));
+        let empty: [u32; 0] = [];
+        assert!(is_bitonic(&empty));
+        assert!(is_bitonic(&[7, 7, 7]));
+    }
+
+    #[test]
+    fn reverse_in_place_odd_and_even() {
+        let mut x = vec![1, 2, 3, 4, 5];
+        reverse_in_place(&mut x);
+        assert_eq!(x, vec![5, 4, 3, 2, 1]);
+
+        let mut x = vec![1, 2, 3, 4];
+        reverse_in_place(&mut x);
+        assert_eq!(x, vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn power_of_two_exponents() {
+        assert_eq!(power_of_two_exponent(1), Some(0));
+        assert_eq!(power_of_two_exponent(2), Some(1));
+        assert_eq!(power_of_two_exponent(1024), Some(10));
+        assert_eq!(power_of_two_exponent(1023), None);
+        assert_eq!(power_of_two_exponent(0), None);
+    }
+
+    #[test]
+    fn count_inversions_of_sequences() {
+        assert_eq!(count_inversions(&[1, 2, 3, 4]), 0);
+        assert_eq!(count_inversions(&[4, 3, 2, 1]), 6);
+        assert_eq!(count_inversions(&[2, 4, 1, 3, 5]), 3);
+        // 等しい要素の組は転倒に数えない
+        assert_eq!(count_inversions(&[2, 2, 1]), 2);
+
+        // すべての組を調べた結果と一致する
+        let x = new_u32_vec(200);
+        let mut expected = 0;
+        for i in 0..x.len() {
+            for j in i + 1..x.len() {
+                if x[i] > x[j] {
+                    expected += 1;
+                }
+            }
+        }
+        assert_eq!(count_inversions(&x), expected);
+    }
 }